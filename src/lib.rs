@@ -1,6 +1,15 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use std::ops::Range;
+use std::rc::Rc;
+
 pub type ParserFunction<'a, I, O> = dyn Fn(I) -> Option<(O, Option<I>)> + 'a;
 
-pub struct Parser<'a, I, O>(Box<ParserFunction<'a, &'a [I], O>>);
+pub struct Parser<'a, I, O>(
+    Box<ParserFunction<'a, &'a [I], O>>,
+    Option<Box<AllMatches<'a, I, O>>>,
+);
 pub type StringParser<'a, O> = Parser<'a, char, O>;
 
 pub type ThenMaybe<'a, I, O, O2> = Parser<'a, I, (O, Option<O2>)>;
@@ -10,25 +19,192 @@ pub type Many1<'a, I, O> = Parser<'a, I, Option<Vec<O>>>;
 pub type Or<'a, I, O> = Parser<'a, I, O>;
 pub type Skip<'a, I> = Parser<'a, I, ()>;
 
+/// Every match of an alternation, see `Parser::all_matches`
+type AllMatches<'a, I, O> = dyn Fn(&'a [I]) -> Vec<(O, Option<&'a [I]>)> + 'a;
+
+/// Failure of `Parser::parse_with_error`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// Offset of the furthest position a parser failed at
+    pub offset: usize,
+    /// Labels of the parsers that failed at `offset`
+    pub expected: Vec<&'static str>,
+    /// Names of the `Parser::context`s that were running at `offset`, outermost first
+    pub context: Vec<&'static str>,
+    /// Input around `offset`, when the failure happened inside of a `Parser::snapshot`
+    pub snippet: Option<Snippet>,
+}
+
+/// Characters around the position of a `ParseError`, see `Parser::snapshot`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snippet {
+    /// Up to `radius` characters right before the failure
+    pub before: String,
+    /// Up to `radius` characters starting at the failure
+    pub after: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "parse error at offset {}", self.offset)?;
+        if !self.expected.is_empty() {
+            write!(f, ", expected {}", self.expected.join(" or "))?;
+        }
+        if !self.context.is_empty() {
+            write!(f, " in {}", self.context.join(" > "))?;
+        }
+        if let Some(snippet) = &self.snippet {
+            write!(f, " near `{}|{}`", snippet.before, snippet.after)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Value of one of two alternatives with different types, see `Parser::either`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Either<L, R> {
+    Left(L),
+    Right(R),
+}
+
+/// Value of a parser together with the offsets of the input it consumed, see `Parser::spanned`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<O> {
+    pub value: O,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A single invocation of a parser wrapped with `Parser::trace`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEvent {
+    pub tag: &'static str,
+    /// Offset the parser was invoked at, relative to the start of the input given to `parse`
+    pub offset: usize,
+    pub outcome: TraceOutcome,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceOutcome {
+    Matched { consumed: usize },
+    Failed,
+}
+
+/// An invocation of a parser recorded by `Parser::parse_with_tree`, together with the invocations
+/// that happened inside of it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseTree {
+    /// Label of the parser (see `Parser::label`), or the name of the combinator: `and`, `or`,
+    /// `choice` or `many`
+    pub tag: &'static str,
+    /// Offset the parser was invoked at, relative to the start of the input given to `parse`
+    pub offset: usize,
+    pub outcome: TraceOutcome,
+    pub children: Vec<ParseTree>,
+}
+
+/// Counters collected by `Parser::parse_with_stats`
+#[cfg(feature = "instrument")]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ParseStats {
+    /// How many times any parser was invoked
+    pub invocations: usize,
+    /// How many alternatives of `or` and `choice` were tried
+    pub branches: usize,
+}
+
+/// Zero-width match caught by `Parser::many_checked`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stall {
+    /// Offset the inner parser matched without consuming anything at
+    pub offset: usize,
+}
+
+/// Failure of `Parser::limited` when the parse needed more parser invocations than allowed
+#[cfg(feature = "instrument")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepLimitExceeded {
+    pub max_steps: usize,
+}
+
+#[cfg(feature = "instrument")]
+impl std::fmt::Display for StepLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "parse exceeded the limit of {} steps", self.max_steps)
+    }
+}
+
+#[cfg(feature = "instrument")]
+impl std::error::Error for StepLimitExceeded {}
+
+/// Why `Parser::parse_all` didn't produce a value
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrailingInput<'a, I> {
+    /// The parser didn't match at all
+    NoMatch,
+    /// The parser matched but left this input unconsumed
+    Trailing(&'a [I]),
+}
+
+/// Result of `Parser::classify`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseOutcome<'a, I, O> {
+    /// The parser didn't match at all
+    NoMatch,
+    /// The parser matched but left `rest` unconsumed
+    Partial { value: O, rest: &'a [I] },
+    /// The parser matched and consumed the whole input
+    Complete(O),
+}
+
 impl<'a, I: 'a, O: 'a> Parser<'a, I, O> {
     /// Create a new parser from the specified function
     pub fn new<F>(f: F) -> Self
     where
         F: Fn(&'a [I]) -> Option<(O, Option<&'a [I]>)> + 'a,
     {
-        Self(Box::new(f))
+        #[cfg(feature = "instrument")]
+        let f = move |input: &'a [I]| {
+            count(|stats| stats.invocations += 1);
+            if !step() {
+                return None;
+            }
+            f(input)
+        };
+        Self(Box::new(f), None)
+    }
+
+    /// Gives the parser a way to find every match of its alternatives for `all_matches`
+    fn with_all_matches<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&'a [I]) -> Vec<(O, Option<&'a [I]>)> + 'a,
+    {
+        self.1 = Some(Box::new(f));
+        self
+    }
+
+    fn matches(&self, input: &'a [I]) -> Vec<(O, Option<&'a [I]>)> {
+        match &self.1 {
+            Some(all) => all(input),
+            None => self.0(input).into_iter().collect(),
+        }
+    }
+
+    /// Gives back the function inside of the parser for building custom combinators. It follows
+    /// the same convention as `Parser::new`: `None` when there is no match, otherwise the value and
+    /// the remainder, which is `None` when the input was consumed completely. Calling it directly
+    /// skips what `parse` sets up, so offsets and per parse caches are only right when it's called
+    /// from inside of another parser
+    pub fn into_fn(self) -> Box<ParserFunction<'a, &'a [I], O>> {
+        self.0
     }
 
     /// This parser just skips the parsed input by consuming the string and returning unit in the
     /// output field
     pub fn skip(self) -> Skip<'a, I> {
-        Parser::new(move |input: &'a [I]| {
-            if let Some((_p, r)) = self.0(input) {
-                Some(((), r))
-            } else {
-                None
-            }
-        })
+        Parser::new(move |input: &'a [I]| self.0(input).map(|(_p, r)| ((), r)))
     }
 
     /// If the function doesnt match then this parser doesn't consume the input and passes it
@@ -37,28 +213,194 @@ impl<'a, I: 'a, O: 'a> Parser<'a, I, O> {
         Parser::new(move |input: &'a [I]| match self.0(input) {
             Some((p, Some(r))) => Some((Some(p), Some(r))),
             Some((p, None)) => Some((Some(p), None)),
-            None => Some((None, Some(input))),
+            None => recovered(Some((None, Some(input)))),
         })
     }
 
     pub fn or(self, other: Parser<'a, I, O>) -> Or<'a, I, O> {
+        let (first, second) = (Rc::new(self), Rc::new(other));
+        let (all_first, all_second) = (first.clone(), second.clone());
+        Parser::new(move |input: &'a [I]| {
+            node("or", input, || {
+                COMMITTED.set(false);
+                #[cfg(feature = "instrument")]
+                count(|stats| stats.branches += 1);
+                if let Some((p, r)) = first.0(input) {
+                    return Some((p, r));
+                }
+                if COMMITTED.get() {
+                    return None;
+                }
+                #[cfg(feature = "instrument")]
+                count(|stats| stats.branches += 1);
+                second.0(input)
+            })
+        })
+        .with_all_matches(move |input| {
+            let mut matches = all_first.matches(input);
+            matches.extend(all_second.matches(input));
+            matches
+        })
+    }
+
+    /// Same as `or` for alternatives with different output types, `f` and `g` map the value of the
+    /// alternative that matched into a common type
+    pub fn or_map<O2: 'a, NewO: 'a, F, G>(
+        self,
+        other: Parser<'a, I, O2>,
+        f: F,
+        g: G,
+    ) -> Parser<'a, I, NewO>
+    where
+        F: Fn(O) -> NewO + 'a,
+        G: Fn(O2) -> NewO + 'a,
+    {
+        Parser::new(move |input: &'a [I]| {
+            COMMITTED.set(false);
+            match self.0(input) {
+                Some((o, r)) => Some((f(o), r)),
+                None if COMMITTED.get() => None,
+                None => other.0(input).map(|(o, r)| (g(o), r)),
+            }
+        })
+    }
+
+    /// Same as `or` for alternatives with different output types, the value is kept as
+    /// `Either::Left` when `self` matches and `Either::Right` when `other` matches
+    pub fn either<O2: 'a>(self, other: Parser<'a, I, O2>) -> Parser<'a, I, Either<O, O2>> {
+        self.or_map(other, Either::Left, Either::Right)
+    }
+
+    /// Same as `or` but the alternative is built by `f` from the `ParseError` of `self`, so the
+    /// fallback can depend on why `self` failed. The offset of the error is relative to the start
+    /// of the input given to `parse`
+    pub fn or_with<F>(self, f: F) -> Parser<'a, I, O>
+    where
+        F: Fn(ParseError) -> Parser<'a, I, O> + 'a,
+    {
+        Parser::new(move |input: &'a [I]| {
+            COMMITTED.set(false);
+            let (result, tracker) = tracking(|| self.0(input));
+            if result.is_some() {
+                return result;
+            }
+            forward(&tracker);
+            if COMMITTED.get() {
+                return None;
+            }
+            let addr = tracker.furthest.unwrap_or(input.as_ptr() as usize);
+            f(tracker.into_error(position(input) + offset_of(input, addr))).0(input)
+        })
+    }
+
+    /// Calls `f` with the input when `self` fails, so it can recover with a value and a remainder
+    /// of its choice
+    pub fn on_failure<F>(self, f: F) -> Parser<'a, I, O>
+    where
+        F: Fn(&'a [I]) -> Option<(O, Option<&'a [I]>)> + 'a,
+    {
+        Parser::new(move |input: &'a [I]| self.0(input).or_else(|| f(input).map(recovered)))
+    }
+
+    /// Gives `Ok` with the value of `self`, or when `self` fails skips the input up to and
+    /// including the next match of `sync` and gives `Err(err)`. Without any `sync` match left the
+    /// rest of the input is skipped, so this never fails
+    pub fn recoverable<E: Clone + 'a>(
+        self,
+        err: E,
+        sync: Skip<'a, I>,
+    ) -> Parser<'a, I, Result<O, E>> {
+        Parser::new(move |input: &'a [I]| {
+            if let Some((o, r)) = self.0(input) {
+                return Some((Ok(o), r));
+            }
+            let r = (0..input.len()).find_map(|i| sync.0(&input[i..]).map(|(_, r)| r));
+            recovered(Some((Err(err.clone()), r.flatten())))
+        })
+    }
+
+    /// Returns `fallback` without consuming anything when `self` doesn't match
+    pub fn or_value(self, fallback: O) -> Parser<'a, I, O>
+    where
+        O: Clone,
+    {
+        Parser::new(move |input: &'a [I]| {
+            self.0(input).or_else(|| recovered(Some((fallback.clone(), Some(input)))))
+        })
+    }
+
+    /// Returns `value` when the input is empty and runs `self` otherwise. A failure of `self` on
+    /// non empty input is still a failure
+    pub fn or_empty(self, value: O) -> Parser<'a, I, O>
+    where
+        O: Clone,
+    {
         Parser::new(move |input: &'a [I]| {
-            if let Some((p, r)) = self.0(input) {
-                return Some((p, r));
+            if input.is_empty() {
+                return Some((value.clone(), Some(input)));
             }
-            other.0(input)
+            self.0(input)
+        })
+    }
+
+    /// Same as `choice` with three alternatives but without building a `Vec`
+    pub fn or3(self, b: Parser<'a, I, O>, c: Parser<'a, I, O>) -> Or<'a, I, O> {
+        self.or(b).or(c)
+    }
+
+    /// Same as `choice` with four alternatives but without building a `Vec`
+    pub fn or4(
+        self,
+        b: Parser<'a, I, O>,
+        c: Parser<'a, I, O>,
+        d: Parser<'a, I, O>,
+    ) -> Or<'a, I, O> {
+        self.or(b).or(c).or(d)
+    }
+
+    /// Runs `self` only if `guard` doesn't match at the current position. The guard never
+    /// consumes anything
+    pub fn unless<G: 'a>(self, guard: Parser<'a, I, G>) -> Parser<'a, I, O> {
+        Parser::new(move |input: &'a [I]| match guard.0(input) {
+            Some(_) => None,
+            None => {
+                COMMITTED.set(false);
+                self.0(input)
+            }
+        })
+    }
+
+    /// Runs `self` only if `guard` matches at the current position, failing right away otherwise.
+    /// The guard never consumes anything, so a cheap lookahead can gate an expensive parser
+    pub fn when_peek<G: 'a>(self, guard: Parser<'a, I, G>) -> Parser<'a, I, O> {
+        Parser::new(move |input: &'a [I]| match guard.0(input) {
+            Some(_) => self.0(input),
+            None => None,
         })
     }
 
     /// This combinator requires to match both parsers and if it doesn't match then it will fail
     pub fn and<O2: 'a>(self, other: Parser<'a, I, O2>) -> And<'a, I, O, O2> {
-        Parser::new(move |input: &'a [I]| match self.0(input) {
-            Some((p1, Some(r))) => match other.0(r) {
-                Some((p2, r)) => Some(((p1, p2), r)),
+        Parser::new(move |input: &'a [I]| {
+            node("and", input, || match self.0(input) {
+                Some((p1, Some(r))) => other.0(r).map(|(p2, r)| ((p1, p2), r)),
+                Some((_p1, None)) => fail_eof(input),
                 None => None,
-            },
-            Some((_p1, None)) => None,
-            None => None,
+            })
+        })
+    }
+
+    /// Matches `self` surrounded by any number of `filler` matches on both sides, like whitespace
+    /// or comments, and keeps only the value of `self`
+    pub fn pad<P: 'a>(self, filler: Parser<'a, I, P>) -> Parser<'a, I, O> {
+        let filler = filler.many();
+        Parser::new(move |input: &'a [I]| {
+            let (_, r) = filler.0(input)?;
+            let (o, r) = self.0(r.unwrap_or(&input[input.len()..]))?;
+            match r {
+                Some(r) => filler.0(r).map(|(_, r)| (o, r)),
+                None => Some((o, None)),
+            }
         })
     }
 
@@ -68,25 +410,279 @@ impl<'a, I: 'a, O: 'a> Parser<'a, I, O> {
         Parser::new(move |input| match self.0(input) {
             Some((p1, Some(r))) => match other.0(r) {
                 Some((p2, r1)) => Some(((p1, Some(p2)), r1)),
-                None => Some(((p1, None), Some(r))),
+                None => recovered(Some(((p1, None), Some(r)))),
             },
             Some((p1, None)) => Some(((p1, None), None)),
             None => None,
         })
     }
 
-    /// Matches zero or more elements based on the inside parser
+    /// Matches `self` followed by the required `trailing` parser and keeps only the value of `self`
+    pub fn then_skip<O2: 'a>(self, trailing: Parser<'a, I, O2>) -> Parser<'a, I, O> {
+        self.and(trailing).map(|(o, _)| o)
+    }
+
+    /// Matches `self` and then the parser that `f` builds from a reference to its value, keeping
+    /// both values. Like `and`, it fails when `self` consumed the whole input
+    pub fn chain<O2: 'a, F>(self, f: F) -> Parser<'a, I, (O, O2)>
+    where
+        F: Fn(&O) -> Parser<'a, I, O2> + 'a,
+    {
+        Parser::new(move |input: &'a [I]| match self.0(input)? {
+            (p1, Some(r)) => f(&p1).0(r).map(|(p2, r)| ((p1, p2), r)),
+            (_, None) => fail_eof(input),
+        })
+    }
+
+    /// Fails without consuming anything when `self` consumes fewer than `min` or more than `max`
+    /// elements
+    pub fn length_between(self, min: usize, max: usize) -> Parser<'a, I, O> {
+        Parser::new(move |input: &'a [I]| {
+            let (o, r) = self.0(input)?;
+            if !(min..=max).contains(&consumed(input, r)) {
+                return fail(input);
+            }
+            Some((o, r))
+        })
+    }
+
+    /// Runs `self` and `other` both from the same position, unlike `and` which runs them one after
+    /// the other. Both must match and consume the same number of elements, otherwise it fails
+    pub fn zip<O2: 'a>(self, other: Parser<'a, I, O2>) -> Parser<'a, I, (O, O2)> {
+        Parser::new(move |input: &'a [I]| {
+            let (a, r) = self.0(input)?;
+            let (b, r2) = other.0(input)?;
+            if consumed(input, r) != consumed(input, r2) {
+                return fail(input);
+            }
+            Some(((a, b), r))
+        })
+    }
+
+    /// Matches zero or more elements based on the inside parser. A match that doesn't consume
+    /// anything ends the repetition, so zero-width parsers can't loop forever
     pub fn many(self) -> Many<'a, I, O> {
+        Parser::new(move |start: &'a [I]| {
+            node("many", start, || {
+                let mut input = start;
+                let mut elements = vec![];
+                while let Some((p, r)) = self.0(input) {
+                    if !progressed(input, r) {
+                        break;
+                    }
+                    elements.push(p);
+                    match r {
+                        Some(r) => input = r,
+                        None => return Some((elements, None)),
+                    }
+                }
+                recovered(Some((elements, Some(input))))
+            })
+        })
+    }
+
+    /// Same as `many` but collects the elements into any `FromIterator` collection
+    pub fn collect<C: FromIterator<O> + 'a>(self) -> Parser<'a, I, C> {
+        Parser::new(move |input: &'a [I]| {
+            let mut rest = Some(input);
+            let collection = std::iter::from_fn(|| {
+                let input = rest?;
+                let (p, r) = self.0(input)?;
+                if !progressed(input, r) {
+                    return None;
+                }
+                rest = r;
+                Some(p)
+            })
+            .collect();
+            recovered(Some((collection, rest)))
+        })
+    }
+
+    /// Matches zero or more elements separated by `sep` and collects them into any `FromIterator`
+    /// collection. A trailing separator that isn't followed by an element is not consumed
+    pub fn sep_by_collect<S: 'a, C: FromIterator<O> + 'a>(
+        self,
+        sep: Parser<'a, I, S>,
+    ) -> Parser<'a, I, C> {
+        Parser::new(move |input: &'a [I]| {
+            let mut rest = Some(input);
+            let mut first = true;
+            let collection = std::iter::from_fn(|| {
+                let input = rest?;
+                let element_input = if first {
+                    first = false;
+                    input
+                } else {
+                    sep.0(input)?.1?
+                };
+                let (p, r) = self.0(element_input)?;
+                if !progressed(input, r) {
+                    return None;
+                }
+                rest = r;
+                Some(p)
+            })
+            .collect();
+            recovered(Some((collection, rest)))
+        })
+    }
+
+    /// Same as `sep_by_collect` into a `Vec` but keeps going past elements that fail. A failing
+    /// element gives `err` of its offset (relative to the start of the input given to `parse`) and
+    /// the input is skipped up to the next position where `sep` matches, where the list goes on.
+    /// Without any `sep` match left the rest of the input is skipped. A separator that doesn't
+    /// consume anything or isn't followed by any input ends the list
+    pub fn sep_by_collecting_errors<S: 'a, E: 'a, F>(
+        self,
+        sep: Parser<'a, I, S>,
+        err: F,
+    ) -> Parser<'a, I, (Vec<O>, Vec<E>)>
+    where
+        F: Fn(usize) -> E + 'a,
+    {
+        Parser::new(move |input: &'a [I]| {
+            let (mut elements, mut errors) = (vec![], vec![]);
+            let mut rest = Some(input).filter(|input| !input.is_empty());
+            let mut first = true;
+            while let Some(at) = rest {
+                let element_input = if first {
+                    first = false;
+                    at
+                } else {
+                    match sep.0(at) {
+                        Some((_, Some(r))) if progressed(at, Some(r)) && !r.is_empty() => r,
+                        _ => break,
+                    }
+                };
+                match self.0(element_input) {
+                    Some((p, r)) => {
+                        elements.push(p);
+                        rest = r;
+                    }
+                    None => {
+                        errors.push(err(position(element_input)));
+                        rest = (0..element_input.len())
+                            .map(|i| &element_input[i..])
+                            .find(|r| sep.0(r).is_some());
+                    }
+                }
+            }
+            recovered(Some(((elements, errors), rest)))
+        })
+    }
+
+    /// Folds zero or more elements into an accumulator starting from `init`. When `f` returns
+    /// `Err` the whole parser fails at the element that was rejected
+    #[allow(clippy::result_unit_err)]
+    pub fn fold_many_result<Acc, F>(self, init: Acc, f: F) -> Parser<'a, I, Acc>
+    where
+        Acc: Clone + 'a,
+        F: Fn(Acc, O) -> Result<Acc, ()> + 'a,
+    {
         Parser::new(move |mut input: &'a [I]| {
-            let mut elements = vec![];
+            let mut acc = init.clone();
             while let Some((p, r)) = self.0(input) {
-                elements.push(p);
+                if !progressed(input, r) {
+                    break;
+                }
+                let Ok(next) = f(acc, p) else {
+                    return fail(input);
+                };
+                acc = next;
                 match r {
                     Some(r) => input = r,
-                    None => return Some((elements, None)),
+                    None => return Some((acc, None)),
+                }
+            }
+            recovered(Some((acc, Some(input))))
+        })
+    }
+
+    /// Folds zero or more elements from right to left, so the last element is combined with
+    /// `init` first. Builds right nested structures like cons lists
+    pub fn fold_many_right<Acc, F>(self, init: Acc, f: F) -> Parser<'a, I, Acc>
+    where
+        Acc: Clone + 'a,
+        F: Fn(O, Acc) -> Acc + 'a,
+    {
+        self.many()
+            .map(move |elements| elements.into_iter().rfold(init.clone(), |acc, o| f(o, acc)))
+    }
+
+    /// Matches one or more elements separated by `sep` and keeps the separators, giving
+    /// `[element, sep, element, ...]`. A trailing separator that isn't followed by an element is
+    /// not consumed, and it stops when a separator and an element together don't consume anything
+    pub fn interleave(self, sep: Parser<'a, I, O>) -> Many<'a, I, O> {
+        Parser::new(move |input: &'a [I]| {
+            let (first, mut r) = self.0(input)?;
+            let mut elements = vec![first];
+            while let Some(input) = r {
+                let Some((s, Some(after_sep))) = sep.0(input) else {
+                    break;
+                };
+                let Some((p, next)) = self.0(after_sep) else {
+                    break;
+                };
+                if !progressed(input, next) {
+                    break;
+                }
+                elements.extend([s, p]);
+                r = next;
+            }
+            recovered(Some((elements, r)))
+        })
+    }
+
+    /// Matches as many elements as possible and then `then`. When `then` doesn't match it gives
+    /// back one element at a time until it does, like a greedy `.*` in a regex, and fails if
+    /// `then` doesn't match even after giving back every element
+    pub fn many_backtrack<O2: 'a>(self, then: Parser<'a, I, O2>) -> Parser<'a, I, (Vec<O>, O2)> {
+        Parser::new(move |input: &'a [I]| {
+            let mut elements = vec![];
+            let mut positions = vec![Some(input)];
+            let mut r = Some(input);
+            while let Some(input) = r {
+                let Some((p, next)) = self.0(input).filter(|(_, next)| progressed(input, *next))
+                else {
+                    break;
+                };
+                elements.push(p);
+                positions.push(next);
+                r = next;
+            }
+            COMMITTED.set(false);
+            while let Some(position) = positions.pop() {
+                if let Some((o, r)) = position.and_then(|position| then.0(position)) {
+                    elements.truncate(positions.len());
+                    return Some(((elements, o), r));
                 }
+                if COMMITTED.get() {
+                    return None;
+                }
+            }
+            None
+        })
+    }
+
+    /// Matches between `min` and `max` elements. It fails (consuming nothing) when fewer than
+    /// `min` elements match and stops after `max` even if more would match
+    pub fn many_m_n(self, min: usize, max: usize) -> Many<'a, I, O> {
+        Parser::new(move |input: &'a [I]| {
+            let mut elements = vec![];
+            let mut r = Some(input);
+            while let Some(input) = r.filter(|_| elements.len() < max) {
+                let Some((p, next)) = self.0(input).filter(|(_, next)| progressed(input, *next))
+                else {
+                    break;
+                };
+                elements.push(p);
+                r = next;
             }
-            Some((elements, Some(input)))
+            if elements.len() < min {
+                return None;
+            }
+            recovered(Some((elements, r)))
         })
     }
 
@@ -95,6 +691,9 @@ impl<'a, I: 'a, O: 'a> Parser<'a, I, O> {
         Parser::new(move |mut input: &'a [I]| {
             let mut elements = vec![];
             while let Some((p, r)) = self.0(input) {
+                if !progressed(input, r) {
+                    break;
+                }
                 elements.push(p);
                 match r {
                     Some(r) => input = r,
@@ -104,214 +703,3828 @@ impl<'a, I: 'a, O: 'a> Parser<'a, I, O> {
             if elements.is_empty() {
                 None
             } else {
-                Some((Some(elements), Some(input)))
+                recovered(Some((Some(elements), Some(input))))
             }
         })
     }
 
-    /// Tries the combinators in order, and either returns the first match or None
-    pub fn choice(possibilities: Vec<Parser<'a, I, O>>) -> Parser<'a, I, O> {
-        Parser::new(move |input: &[I]| {
-            for parser in &possibilities {
-                if let Some((p, r)) = parser.0(input) {
-                    return Some((p, r));
+    /// Same as `many` but calls `cb` with the number of elements matched so far after every
+    /// `every` elements (never when `every` is 0). The callback doesn't affect the result
+    pub fn many_with<F>(self, every: usize, cb: F) -> Many<'a, I, O>
+    where
+        F: Fn(usize) + 'a,
+    {
+        Parser::new(move |mut input: &'a [I]| {
+            let mut elements = vec![];
+            while let Some((p, r)) = self.0(input) {
+                if !progressed(input, r) {
+                    break;
+                }
+                elements.push(p);
+                if every != 0 && elements.len() % every == 0 {
+                    cb(elements.len());
+                }
+                match r {
+                    Some(r) => input = r,
+                    None => return Some((elements, None)),
                 }
             }
-            return None;
+            recovered(Some((elements, Some(input))))
         })
     }
 
-    pub fn map<F, NewO: 'a>(self, f: F) -> Parser<'a, I, NewO>
+    /// Same as `many` but pairs every element with its zero based index
+    pub fn many_indexed(self) -> Many<'a, I, (usize, O)> {
+        self.many()
+            .map(|elements| elements.into_iter().enumerate().collect())
+    }
+
+    /// Same as `many` but pairs every element with its start offset, relative to the start of the
+    /// input given to `parse`
+    pub fn many_spanned(self) -> Many<'a, I, (O, usize)> {
+        self.spanned().many().map(|elements| {
+            elements
+                .into_iter()
+                .map(|Spanned { value, start, .. }| (value, start))
+                .collect()
+        })
+    }
+
+    /// Same as `many` but a match that doesn't consume anything gives `Err(Stall)` instead of
+    /// silently ending the repetition, the remainder is then the input at the stall
+    pub fn many_checked(self) -> Parser<'a, I, Result<Vec<O>, Stall>> {
+        Parser::new(move |mut input: &'a [I]| {
+            let mut elements = vec![];
+            while let Some((p, r)) = self.0(input) {
+                if !progressed(input, r) {
+                    let offset = position(input);
+                    return Some((Err(Stall { offset }), Some(input)));
+                }
+                elements.push(p);
+                match r {
+                    Some(r) => input = r,
+                    None => return Some((Ok(elements), None)),
+                }
+            }
+            recovered(Some((Ok(elements), Some(input))))
+        })
+    }
+
+    /// Same as `many` but pairs every element with how many of the last `k` elements (itself
+    /// included) satisfy `pred`
+    pub fn many_windowed<F>(self, k: usize, pred: F) -> Many<'a, I, (O, usize)>
     where
-        F: Fn(O) -> NewO + 'a,
+        F: Fn(&O) -> bool + 'a,
     {
-        Parser::new(move |input: &[I]| self.0(input).map(|(o, r)| (f(o), r)))
+        self.many().map(move |elements| {
+            let mut window = VecDeque::with_capacity(k);
+            let mut hits = 0;
+            elements
+                .into_iter()
+                .map(|o| {
+                    let hit = pred(&o);
+                    if k > 0 {
+                        if window.len() == k && window.pop_front() == Some(true) {
+                            hits -= 1;
+                        }
+                        window.push_back(hit);
+                        hits += hit as usize;
+                    }
+                    (o, hits)
+                })
+                .collect()
+        })
     }
-    pub fn filter<F>(self, f: F) -> Parser<'a, I, O>
+
+    /// Same as `many` but maps every element with `f` and stops at the first one it gives `None`
+    /// for, that element isn't consumed
+    pub fn many_map_while<F, NewO: 'a>(self, f: F) -> Many<'a, I, NewO>
     where
-        F: Fn(&O) -> bool + 'a,
+        F: Fn(&O) -> Option<NewO> + 'a,
     {
-        Parser::new(move |input: &[I]| self.0(input).filter(|(o, _r)| f(o)))
+        Parser::new(move |mut input: &'a [I]| {
+            let mut elements = vec![];
+            while let Some((p, r)) = self.0(input) {
+                if !progressed(input, r) {
+                    break;
+                }
+                let Some(p) = f(&p) else {
+                    break;
+                };
+                elements.push(p);
+                match r {
+                    Some(r) => input = r,
+                    None => return Some((elements, None)),
+                }
+            }
+            recovered(Some((elements, Some(input))))
+        })
     }
 
-    pub fn parse(&self, input: &'a [I]) -> Option<(O, Option<&'a [I]>)> {
-        self.0(input)
+    /// Same as `many` but a run of equal consecutive elements is only kept once, the repeated
+    /// ones are still consumed
+    pub fn many_dedup(self) -> Many<'a, I, O>
+    where
+        O: PartialEq,
+    {
+        self.many().map(|mut elements| {
+            elements.dedup();
+            elements
+        })
     }
-}
 
-pub fn satisfy<'a, F>(f: F) -> StringParser<'a, char>
-where
-    F: Fn(char) -> bool + 'a,
-{
-    Parser::new(move |input: &[char]| match input.split_at_checked(1) {
-        Some((p, r)) if !p.is_empty() && f(p[0]) && !r.is_empty() => Some((p[0], Some(r))),
-        Some((p, r)) if !p.is_empty() && f(p[0]) && r.is_empty() => Some((p[0], None)),
-        _ => None,
-    })
+    /// Matches `self` and then requires that there is no input left
+    pub fn expect_eof(self) -> Parser<'a, I, O> {
+        Parser::new(move |input: &'a [I]| match self.0(input) {
+            Some((o, None)) => Some((o, None)),
+            Some((o, Some([]))) => Some((o, None)),
+            Some((_o, Some(r))) => fail(r),
+            None => None,
+        })
+    }
+
+    /// Matches one or more elements until `end` matches and returns them together with the value of
+    /// `end`. The first element is always parsed by `self`, after that `end` is tried first on
+    /// every iteration
+    pub fn many1_till<O2: 'a>(self, end: Parser<'a, I, O2>) -> Parser<'a, I, (Vec<O>, O2)> {
+        Parser::new(move |input: &'a [I]| {
+            let (first, mut r) = self.0(input)?;
+            let mut elements = vec![first];
+            loop {
+                let Some(input) = r else {
+                    return fail_eof(input);
+                };
+                if let Some((e, r)) = end.0(input) {
+                    return Some(((elements, e), r));
+                }
+                if COMMITTED.get() {
+                    return None;
+                }
+                let (p, next) = self.0(input)?;
+                if !progressed(input, next) {
+                    return None;
+                }
+                elements.push(p);
+                r = next;
+            }
+        })
+    }
+
+    /// Names what `self` matches for error reporting, the name ends up in `ParseError::expected`
+    /// when `self` fails right where it started
+    pub fn label(self, name: &'static str) -> Parser<'a, I, O> {
+        let parser = Rc::new(self);
+        let all = parser.clone();
+        Parser::new(move |input: &'a [I]| {
+            let result = node(name, input, || parser.0(input));
+            if result.is_none() {
+                record_failure(input.as_ptr() as usize, Some(name));
+            }
+            result
+        })
+        .with_all_matches(move |input| all.matches(input))
+    }
+
+    /// Marks `self` as required, for positions after which the grammar is committed (like a
+    /// closing parenthesis). When `self` fails `parse_with_error` reports `expected {what}` where
+    /// `self` started, and the enclosing alternations (`or`, `or_map`, `either`, `or_with`,
+    /// `choice`, `choice_ref` and `choice_peek_longest`) fail without trying their other
+    /// alternatives. Combinators that recover from the failure (like `maybe`, `or_value` or the
+    /// `many` family) drop the commit again, so the alternations around them backtrack as usual
+    pub fn expect(self, what: &'static str) -> Parser<'a, I, O> {
+        Parser::new(move |input: &'a [I]| {
+            let (result, tracker) = tracking(|| self.0(input));
+            if result.is_some() {
+                forward(&tracker);
+                return result;
+            }
+            let error = ParseError {
+                offset: 0,
+                expected: vec![what],
+                context: vec![],
+                snippet: None,
+            };
+            record_error(input.as_ptr() as usize, error);
+            COMMITTED.set(true);
+            None
+        })
+    }
+
+    /// Runs `self` inside of the context `name`. When the furthest failure happens inside of it,
+    /// `ParseError::context` holds the path of nested contexts leading to it, like
+    /// `["document", "array", "number"]`
+    pub fn context(self, name: &'static str) -> Parser<'a, I, O> {
+        Parser::new(move |input: &'a [I]| {
+            let tracked = TRACKER.with_borrow_mut(|tracker| {
+                tracker
+                    .as_mut()
+                    .map(|tracker| tracker.stack.push(name))
+                    .is_some()
+            });
+            let result = self.0(input);
+            if tracked {
+                TRACKER.with_borrow_mut(|tracker| {
+                    if let Some(tracker) = tracker {
+                        tracker.stack.pop();
+                    }
+                });
+            }
+            result
+        })
+    }
+
+    /// Pushes a `TraceEvent` into `sink` every time `self` returns, without changing its result
+    pub fn trace(self, tag: &'static str, sink: Rc<RefCell<Vec<TraceEvent>>>) -> Parser<'a, I, O> {
+        Parser::new(move |input: &'a [I]| {
+            let result = self.0(input);
+            let outcome = match &result {
+                Some((_, r)) => TraceOutcome::Matched {
+                    consumed: consumed(input, *r),
+                },
+                None => TraceOutcome::Failed,
+            };
+            sink.borrow_mut().push(TraceEvent {
+                tag,
+                offset: position(input),
+                outcome,
+            });
+            result
+        })
+    }
+
+    /// Parses `input` with the first of `possibilities` that matches. Same as `choice` but it
+    /// borrows the parsers, so the same parser can be part of several alternation tables
+    pub fn choice_ref(
+        possibilities: &[&Parser<'a, I, O>],
+        input: &'a [I],
+    ) -> Option<(O, Option<&'a [I]>)> {
+        in_run(input, || {
+            COMMITTED.set(false);
+            for parser in possibilities {
+                if let Some((p, r)) = parser.0(input) {
+                    return Some((p, r));
+                }
+                if COMMITTED.get() {
+                    return None;
+                }
+            }
+            None
+        })
+    }
+
+    /// Remembers the positions where `self` failed during the current parse and fails right away
+    /// when it's invoked at one of them again. This is only sound if `self` always gives the same
+    /// result for the same position. A failure that committed (see `expect`) commits again when
+    /// it's replayed
+    pub fn cache_failures(self) -> Parser<'a, I, O> {
+        let failures = RefCell::new((None, HashMap::new()));
+        Parser::new(move |input: &'a [I]| {
+            let run = current_run().map(|run| run.id);
+            let key = (input.as_ptr() as usize, input.len());
+            {
+                let mut failures = failures.borrow_mut();
+                if failures.0 != run {
+                    *failures = (run, HashMap::new());
+                }
+                if let Some(committed) = failures.1.get(&key).filter(|_| run.is_some()) {
+                    COMMITTED.set(*committed);
+                    return None;
+                }
+            }
+            let result = self.0(input);
+            if result.is_none() && run.is_some() {
+                failures.borrow_mut().1.insert(key, COMMITTED.get());
+            }
+            result
+        })
+    }
+
+    /// Tries the combinators in order, and either returns the first match or None
+    pub fn choice(possibilities: Vec<Parser<'a, I, O>>) -> Parser<'a, I, O> {
+        let possibilities = Rc::new(possibilities);
+        let all = possibilities.clone();
+        Parser::new(move |input: &'a [I]| {
+            node("choice", input, || {
+                COMMITTED.set(false);
+                for parser in possibilities.iter() {
+                    #[cfg(feature = "instrument")]
+                    count(|stats| stats.branches += 1);
+                    if let Some((p, r)) = parser.0(input) {
+                        return Some((p, r));
+                    }
+                    if COMMITTED.get() {
+                        return None;
+                    }
+                }
+                None
+            })
+        })
+        .with_all_matches(move |input| {
+            all.iter()
+                .flat_map(|parser| parser.matches(input))
+                .collect()
+        })
+    }
+
+    /// Tries all of the alternatives and returns the result of the one that consumed the most,
+    /// the earliest one on a tie. Unlike `choice` the order only matters for ties, which is what
+    /// overlapping operators like `<`, `<=` and `<<` need
+    pub fn choice_peek_longest(possibilities: Vec<Parser<'a, I, O>>) -> Parser<'a, I, O> {
+        Parser::new(move |input: &'a [I]| {
+            COMMITTED.set(false);
+            let mut longest: Option<(O, Option<&'a [I]>)> = None;
+            for parser in &possibilities {
+                #[cfg(feature = "instrument")]
+                count(|stats| stats.branches += 1);
+                let Some((o, r)) = parser.0(input) else {
+                    if COMMITTED.get() {
+                        return None;
+                    }
+                    continue;
+                };
+                if longest
+                    .as_ref()
+                    .is_none_or(|(_, best)| consumed(input, r) > consumed(input, *best))
+                {
+                    longest = Some((o, r));
+                }
+            }
+            longest
+        })
+    }
+
+    /// Runs the parsers one after the other and returns all of their values, failing if any of
+    /// them fails. Like `seq!` for a list of parsers built at runtime
+    pub fn sequence(parsers: Vec<Parser<'a, I, O>>) -> Many<'a, I, O> {
+        Parser::new(move |input: &'a [I]| {
+            let mut values = Vec::with_capacity(parsers.len());
+            let mut rest = Some(input);
+            for parser in &parsers {
+                let Some(input) = rest else {
+                    return fail_eof(input);
+                };
+                let (o, r) = parser.0(input)?;
+                values.push(o);
+                rest = r;
+            }
+            Some((values, rest))
+        })
+    }
+
+    pub fn map<F, NewO: 'a>(self, f: F) -> Parser<'a, I, NewO>
+    where
+        F: Fn(O) -> NewO + 'a,
+    {
+        if self.1.is_none() {
+            return Parser::new(move |input: &[I]| self.0(input).map(|(o, r)| (f(o), r)));
+        }
+        let (parser, f) = (Rc::new(self), Rc::new(f));
+        let (all, all_f) = (parser.clone(), f.clone());
+        Parser::new(move |input: &[I]| parser.0(input).map(|(o, r)| (f(o), r))).with_all_matches(
+            move |input| {
+                let matches = all.matches(input).into_iter();
+                matches.map(|(o, r)| (all_f(o), r)).collect()
+            },
+        )
+    }
+    /// Same as `map` but the closure also gets the slice of input that `self` consumed
+    pub fn map_slice<F, NewO: 'a>(self, f: F) -> Parser<'a, I, NewO>
+    where
+        F: Fn(O, &'a [I]) -> NewO + 'a,
+    {
+        Parser::new(move |input: &'a [I]| {
+            let (o, r) = self.0(input)?;
+            Some((f(o, &input[..consumed(input, r)]), r))
+        })
+    }
+
+    /// Wraps the value in a `Spanned` with the offsets of the consumed input, relative to the
+    /// start of the input given to `parse`
+    pub fn spanned(self) -> Parser<'a, I, Spanned<O>> {
+        Parser::new(move |input: &'a [I]| {
+            let (value, r) = self.0(input)?;
+            let start = position(input);
+            let end = start + consumed(input, r);
+            Some((Spanned { value, start, end }, r))
+        })
+    }
+
+    /// Same as `map` but `f` can reject the value with a `ParseError`. The parse then fails where
+    /// `self` started and `parse_with_error` reports that error, with the offset set to that
+    /// position, unless a parser outside of `self` failed further
+    pub fn map_res<F, NewO: 'a>(self, f: F) -> Parser<'a, I, NewO>
+    where
+        F: Fn(O) -> Result<NewO, ParseError> + 'a,
+    {
+        Parser::new(move |input: &'a [I]| {
+            // failures inside of a match of `self` don't matter when `f` rejects it
+            let (result, tracker) = tracking(|| self.0(input));
+            let Some((o, r)) = result else {
+                forward(&tracker);
+                return None;
+            };
+            match f(o) {
+                Ok(o) => {
+                    forward(&tracker);
+                    Some((o, r))
+                }
+                Err(error) => {
+                    record_error(input.as_ptr() as usize, error);
+                    None
+                }
+            }
+        })
+    }
+
+    /// Drops the value and gives the span of the consumed input (see `spanned`) tagged with
+    /// `category`, for syntax highlighting
+    pub fn spans_of(self, category: &'static str) -> Parser<'a, I, (Range<usize>, &'static str)> {
+        self.spanned()
+            .map(move |Spanned { start, end, .. }| (start..end, category))
+    }
+
+    /// Applies `f` to the remainder of a successful match. This is a low level escape hatch, the
+    /// remainder `f` returns should be a suffix of the input (and `None` when nothing is left) or
+    /// other combinators will misbehave
+    pub fn map_remainder<F>(self, f: F) -> Parser<'a, I, O>
+    where
+        F: Fn(Option<&'a [I]>) -> Option<&'a [I]> + 'a,
+    {
+        Parser::new(move |input: &'a [I]| self.0(input).map(|(o, r)| (o, f(r))))
+    }
+
+    pub fn filter<F>(self, f: F) -> Parser<'a, I, O>
+    where
+        F: Fn(&O) -> bool + 'a,
+    {
+        Parser::new(move |input: &[I]| self.0(input).filter(|(o, _r)| f(o)))
+    }
+
+    pub fn parse(&self, input: &'a [I]) -> Option<(O, Option<&'a [I]>)> {
+        in_run(input, || self.0(input))
+    }
+
+    /// Runs the parser from `cursor` and gives the value together with the cursor after the consumed
+    /// input. Offsets are relative to the start of the input the cursor was created on, like
+    /// `Cursor::position`
+    pub fn parse_at(&self, cursor: Cursor<'a, I>) -> Option<(O, Cursor<'a, I>)> {
+        let input = cursor.remaining();
+        let (o, r) = in_run(cursor.input, || self.0(input))?;
+        Some((o, cursor.advance(consumed(input, r))?))
+    }
+
+    /// Same as `parse` but on failure reports the furthest position any parser got to. When
+    /// several alternatives of an `or` or `choice` fail, the one that got furthest wins
+    pub fn parse_with_error(&self, input: &'a [I]) -> Result<(O, Option<&'a [I]>), ParseError> {
+        in_run(input, || {
+            let (result, tracker) = tracking(|| self.0(input));
+            result.ok_or_else(|| {
+                let offset = tracker.furthest.map_or(0, |addr| offset_of(input, addr));
+                tracker.into_error(offset)
+            })
+        })
+    }
+
+    /// Same as `parse` but gives a `Result` for use with `?`. Failures aren't tracked, so the
+    /// error always points at the start of the input, use `parse_with_error` for the furthest
+    /// failure position
+    pub fn parse_or_err(&self, input: &'a [I]) -> Result<(O, Option<&'a [I]>), ParseError> {
+        self.parse(input).ok_or(ParseError {
+            offset: 0,
+            expected: vec![],
+            context: vec![],
+            snippet: None,
+        })
+    }
+
+    /// Same as `parse` but the remainder is always a slice, empty when everything was consumed
+    pub fn parse_normalized(&self, input: &'a [I]) -> Option<(O, &'a [I])> {
+        self.parse(input)
+            .map(|(o, r)| (o, r.unwrap_or(&input[input.len()..])))
+    }
+
+    /// Returns the result of every alternative of `or`/`choice` that matches at the start of
+    /// `input`, instead of only the first one. Alternations nested directly inside of each other or
+    /// wrapped in `map` (so `into_string` too) and `label` are explored, any other parser gives at
+    /// most its single result. This is meant for diagnosing ambiguous grammars, the number of
+    /// results can grow exponentially with the nesting of alternations
+    pub fn all_matches(&self, input: &'a [I]) -> Vec<(O, Option<&'a [I]>)> {
+        in_run(input, || self.matches(input))
+    }
+
+    /// Parses the input and tells apart no match, a match with leftover input and a match that
+    /// consumed everything
+    pub fn classify(&self, input: &'a [I]) -> ParseOutcome<'a, I, O> {
+        match self.parse(input) {
+            Some((o, None)) | Some((o, Some([]))) => ParseOutcome::Complete(o),
+            Some((value, Some(rest))) => ParseOutcome::Partial { value, rest },
+            None => ParseOutcome::NoMatch,
+        }
+    }
+
+    /// Parses the input, requires the whole of it to be consumed and maps the value with `f`
+    pub fn parse_map_complete<F, NewO>(&self, input: &'a [I], f: F) -> Option<NewO>
+    where
+        F: Fn(O) -> NewO,
+    {
+        match self.classify(input) {
+            ParseOutcome::Complete(o) => Some(f(o)),
+            _ => None,
+        }
+    }
+
+    /// Lazily applies `self` over and over on `input`, see `ParseIter`. Offsets like the ones of
+    /// `spanned` are relative to the start of `input` for every item
+    pub fn iter_over(self, input: &'a [I]) -> ParseIter<'a, I, O> {
+        ParseIter {
+            parser: self,
+            input,
+            rest: Some(input),
+        }
+    }
+
+    /// Same as `parse` but also counts how many parsers were invoked and how many alternatives
+    /// were tried, which shows where a grammar backtracks a lot. It needs the `instrument`
+    /// feature, which makes every parser invocation update a thread local counter, so builds
+    /// without it don't pay for the counting
+    #[cfg(feature = "instrument")]
+    #[allow(clippy::type_complexity)]
+    pub fn parse_with_stats(&self, input: &'a [I]) -> (Option<(O, Option<&'a [I]>)>, ParseStats) {
+        struct Restore(Option<ParseStats>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                STATS.set(self.0);
+            }
+        }
+
+        in_run(input, || {
+            let _restore = Restore(STATS.replace(Some(ParseStats::default())));
+            let result = self.0(input);
+            (result, STATS.get().unwrap_or_default())
+        })
+    }
+
+    /// Same as `parse` but gives up with `StepLimitExceeded` once more than `max_steps` parsers
+    /// were invoked, as a safety valve against grammars that backtrack a lot on untrusted input. A
+    /// parse that doesn't match within the limit gives `Ok(None)`. After the limit is hit every
+    /// parser fails right away, so the parse unwinds quickly. It needs the `instrument` feature,
+    /// with it every parser checks a thread local counter when it's invoked, which costs a `Cell`
+    /// read (and an increment inside of `limited`) per invocation
+    #[cfg(feature = "instrument")]
+    #[allow(clippy::type_complexity)]
+    pub fn limited(
+        &self,
+        input: &'a [I],
+        max_steps: usize,
+    ) -> Result<Option<(O, Option<&'a [I]>)>, StepLimitExceeded> {
+        struct Restore(Option<Steps>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                STEPS.set(self.0);
+            }
+        }
+
+        in_run(input, || {
+            let _restore = Restore(STEPS.replace(Some(Steps {
+                taken: 0,
+                max: max_steps,
+            })));
+            let result = self.0(input);
+            match STEPS.get() {
+                Some(steps) if steps.taken > steps.max => Err(StepLimitExceeded { max_steps }),
+                _ => Ok(result),
+            }
+        })
+    }
+
+    /// Same as `parse` but also records every invocation of `and`, `or`, `choice`, `many` and
+    /// labeled parsers as a tree, for finding out why a parse went the way it did. It's meant for
+    /// debugging, every recorded invocation allocates a node
+    #[allow(clippy::type_complexity)]
+    pub fn parse_with_tree(
+        &self,
+        input: &'a [I],
+    ) -> (Option<(O, Option<&'a [I]>)>, Vec<ParseTree>) {
+        struct Restore(Option<Vec<Vec<ParseTree>>>, bool);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                TREE.set(self.0.take());
+                RECORDING.set(self.1);
+            }
+        }
+
+        in_run(input, || {
+            let _restore = Restore(TREE.replace(Some(vec![vec![]])), RECORDING.replace(true));
+            let result = self.0(input);
+            let roots = TREE.take().and_then(|mut frames| frames.pop());
+            (result, roots.unwrap_or_default())
+        })
+    }
+
+    /// Parses the input and requires the whole of it to be consumed, otherwise the unconsumed part
+    /// is returned in the error
+    pub fn parse_all(&self, input: &'a [I]) -> Result<O, TrailingInput<'a, I>> {
+        match self.parse(input) {
+            Some((o, None)) => Ok(o),
+            Some((o, Some([]))) => Ok(o),
+            Some((_o, Some(r))) => Err(TrailingInput::Trailing(r)),
+            None => Err(TrailingInput::NoMatch),
+        }
+    }
+}
+
+/// Iterator that yields the values of a parser applied repeatedly on the remaining input. It ends
+/// when the parser fails, doesn't consume anything or the input is exhausted
+pub struct ParseIter<'a, I, O> {
+    parser: Parser<'a, I, O>,
+    /// The whole input, offsets of every step are relative to its start
+    input: &'a [I],
+    rest: Option<&'a [I]>,
+}
+
+impl<'a, I, O> ParseIter<'a, I, O> {
+    /// The input that wasn't consumed yet, `None` when all of it was
+    pub fn remaining(&self) -> Option<&'a [I]> {
+        self.rest
+    }
+}
+
+impl<'a, I: 'a, O: 'a> Iterator for ParseIter<'a, I, O> {
+    type Item = O;
+
+    fn next(&mut self) -> Option<O> {
+        let input = self.rest.filter(|input| !input.is_empty())?;
+        let (o, r) = in_run(self.input, || self.parser.0(input))?;
+        if !progressed(input, r) {
+            return None;
+        }
+        self.rest = r;
+        Some(o)
+    }
+}
+
+/// Position in the input for building combinators imperatively. A cursor is a plain copy of a
+/// position, so saving one and going on from it later is all there is to backtracking. Parsers run
+/// from a cursor with `Parser::parse_at`
+#[derive(Debug, PartialEq)]
+pub struct Cursor<'a, I> {
+    input: &'a [I],
+    at: usize,
+}
+
+impl<'a, I> Clone for Cursor<'a, I> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, I> Copy for Cursor<'a, I> {}
+
+impl<'a, I> Cursor<'a, I> {
+    /// Cursor at the start of `input`
+    pub fn new(input: &'a [I]) -> Self {
+        Self { input, at: 0 }
+    }
+
+    /// Offset of the cursor in the input it was created with
+    pub fn position(&self) -> usize {
+        self.at
+    }
+
+    /// The input after the cursor, empty at the end of the input
+    pub fn remaining(&self) -> &'a [I] {
+        &self.input[self.at..]
+    }
+
+    /// The input after the cursor as a remainder for `Parser::new`, `None` at the end of the input
+    pub fn rest(&self) -> Option<&'a [I]> {
+        rest(self.input, self.at)
+    }
+
+    /// Moves the cursor `n` elements forward, `None` when fewer are left
+    pub fn advance(self, n: usize) -> Option<Self> {
+        (n <= self.remaining().len()).then_some(Self {
+            at: self.at + n,
+            ..self
+        })
+    }
+}
+
+impl<'a, I: 'a, K: 'a, V: 'a> Parser<'a, I, (K, V)> {
+    /// Matches zero or more key value pairs separated by `sep`, keeping their order and duplicate
+    /// keys unlike `sep_by1_map`. A trailing separator is not consumed
+    pub fn sep_by_pairs<S: 'a>(self, sep: Parser<'a, I, S>) -> Many<'a, I, (K, V)> {
+        self.sep_by_collect(sep)
+    }
+}
+
+impl<'a, I: 'a, K: Eq + Hash + 'a, V: 'a> Parser<'a, I, (K, V)> {
+    /// Matches one or more key value pairs separated by `sep` into a `HashMap`, failing if a key
+    /// appears twice
+    pub fn sep_by1_map<S: 'a>(self, sep: Parser<'a, I, S>) -> Parser<'a, I, HashMap<K, V>> {
+        let pairs = self.sep_by_collect::<S, Vec<_>>(sep);
+        Parser::new(move |input: &'a [I]| {
+            let (pairs, r) = pairs.0(input)?;
+            if pairs.is_empty() {
+                return None;
+            }
+            let mut map = HashMap::with_capacity(pairs.len());
+            for (k, v) in pairs {
+                if map.insert(k, v).is_some() {
+                    return fail(input);
+                }
+            }
+            Some((map, r))
+        })
+    }
+}
+
+impl<'a, I: 'a, O: 'a> Parser<'a, I, Option<O>> {
+    /// Unwraps the optional value, failing without consuming anything when it's `None`. Turns a
+    /// `maybe` back into a required match
+    pub fn required(self) -> Parser<'a, I, O> {
+        Parser::new(move |input: &'a [I]| match self.0(input)? {
+            (Some(o), r) => Some((o, r)),
+            (None, _) => fail(input),
+        })
+    }
+}
+
+impl<'a, I: 'a, O: 'a> Parser<'a, I, Vec<O>> {
+    /// Fails without consuming anything when the `Vec` is empty, so any list combinator can require
+    /// at least one element
+    pub fn non_empty(self) -> Many<'a, I, O> {
+        Parser::new(move |input: &'a [I]| match self.0(input)? {
+            (elements, _) if elements.is_empty() => fail(input),
+            matched => Some(matched),
+        })
+    }
+
+    /// Same as `many` but concatenates the `Vec`s of all of the matches into a single one
+    pub fn many_concat(self) -> Many<'a, I, O> {
+        self.many()
+            .map(|elements| elements.into_iter().flatten().collect())
+    }
+}
+
+impl<'a, I: 'a> Parser<'a, I, String> {
+    /// Gives the strings as `Rc<str>`s, equal strings within a single parse share one allocation,
+    /// so they can be compared with `Rc::ptr_eq`. The interned strings are kept until the next
+    /// parse starts
+    pub fn intern(self) -> Parser<'a, I, Rc<str>> {
+        let interned = RefCell::new((None, HashSet::<Rc<str>>::new()));
+        Parser::new(move |input: &'a [I]| {
+            let (s, r) = self.0(input)?;
+            let run = current_run().map(|run| run.id);
+            let mut interned = interned.borrow_mut();
+            if interned.0 != run {
+                *interned = (run, HashSet::new());
+            }
+            if let Some(shared) = interned.1.get(s.as_str()) {
+                return Some((shared.clone(), r));
+            }
+            let shared: Rc<str> = s.into();
+            interned.1.insert(shared.clone());
+            Some((shared, r))
+        })
+    }
+}
+
+impl<'a, I: 'a> Parser<'a, I, &'a [I]> {
+    /// Parses the slice captured by `self` with `inner`. `inner` has to consume the whole slice,
+    /// otherwise the parser fails
+    pub fn reparse<O2: 'a>(self, inner: Parser<'a, I, O2>) -> Parser<'a, I, O2> {
+        Parser::new(move |input: &'a [I]| {
+            let (captured, r) = self.0(input)?;
+            match inner.0(captured)? {
+                (o, None) | (o, Some([])) => Some((o, r)),
+                (_, Some(leftover)) => fail(leftover),
+            }
+        })
+    }
+}
+
+impl<'a, O: 'a> Parser<'a, char, O> {
+    /// Same as `and` but skips any whitespace in between the two parsers. Whitespace after
+    /// `other` is not consumed
+    pub fn and_ws<O2: 'a>(self, other: Parser<'a, char, O2>) -> And<'a, char, O, O2> {
+        Parser::new(move |input: &'a [char]| {
+            let (p1, r) = self.0(input)?;
+            let Some(r) = r else {
+                return fail_eof(input);
+            };
+            let spaces = r.iter().take_while(|c| c.is_whitespace()).count();
+            let Some(r) = rest(r, spaces) else {
+                return fail_eof(input);
+            };
+            let (p2, r) = other.0(r)?;
+            Some(((p1, p2), r))
+        })
+    }
+
+    /// Same as `parse` but collects the remainder into a `String`, empty when the whole input was
+    /// consumed
+    pub fn parse_remaining_string(&self, input: &'a [char]) -> Option<(O, String)> {
+        self.parse(input)
+            .map(|(o, r)| (o, r.unwrap_or_default().iter().collect()))
+    }
+
+    /// Pairs the value with the input left after `self` collected into a `String` and consumes all
+    /// of it. Nothing is left for the parsers after it, so `and` after this always fails
+    pub fn with_rest_text(self) -> Parser<'a, char, (O, String)> {
+        Parser::new(move |input: &'a [char]| {
+            let (o, r) = self.0(input)?;
+            Some(((o, r.unwrap_or_default().iter().collect()), None))
+        })
+    }
+
+    /// Returns the characters `self` consumed as a `String` instead of its value, unlike
+    /// `into_string` which converts the value
+    pub fn source_text(self) -> StringParser<'a, String> {
+        self.map_slice(|_, consumed| consumed.iter().collect())
+    }
+
+    /// When `self` fails, captures up to `radius` characters on both sides of the furthest failure
+    /// into `ParseError::snippet`. The window is clamped to the input `self` was given
+    pub fn snapshot(self, radius: usize) -> Parser<'a, char, O> {
+        Parser::new(move |input: &'a [char]| {
+            let result = self.0(input);
+            if result.is_none() {
+                TRACKER.with_borrow_mut(|tracker| {
+                    let Some(tracker) = tracker else {
+                        return;
+                    };
+                    let range = input.as_ptr_range();
+                    let Some(addr) = tracker
+                        .furthest
+                        .filter(|addr| (range.start as usize..=range.end as usize).contains(addr))
+                    else {
+                        return;
+                    };
+                    let offset = offset_of(input, addr);
+                    let end = (offset + radius).min(input.len());
+                    tracker.snippet = Some(Snippet {
+                        before: input[offset.saturating_sub(radius)..offset]
+                            .iter()
+                            .collect(),
+                        after: input[offset..end].iter().collect(),
+                    });
+                });
+            }
+            result
+        })
+    }
+
+    /// Runs `self` ignoring the case of ASCII letters: `char`, `string` and the other literal
+    /// parsers of this crate match either case, and predicates like the one of `satisfy` (so
+    /// `char_class` too) match a letter when they accept either of its cases. Only what matches
+    /// changes, values taken from the input keep their original case while `string` still returns
+    /// its own spelling, so `into_string` gives the literal and `source_text` gives the text as it
+    /// was written. Parsers built with `Parser::new` aren't affected
+    pub fn case_insensitive(self) -> Parser<'a, char, O> {
+        struct Restore(bool);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                FOLD_CASE.set(self.0);
+            }
+        }
+
+        Parser::new(move |input: &'a [char]| {
+            let _restore = Restore(FOLD_CASE.replace(true));
+            self.0(input)
+        })
+    }
+
+    /// Pairs the value with the number of `\n` characters in the consumed input
+    pub fn with_line_count(self) -> Parser<'a, char, (O, usize)> {
+        self.map_slice(|o, consumed| (o, consumed.iter().filter(|c| **c == '\n').count()))
+    }
+
+    /// Pairs the value with the UTF-8 length in bytes of the consumed input, for seeking in the
+    /// source the characters were decoded from
+    pub fn with_byte_len(self) -> Parser<'a, char, (O, usize)> {
+        self.map_slice(|o, consumed| (o, consumed.iter().map(|c| c.len_utf8()).sum()))
+    }
+}
+
+impl<'a> Parser<'a, char, char> {
+    /// Matches a character with `self` and counts how many times it's repeated right after,
+    /// returning the character and the length of the run
+    pub fn run(self) -> StringParser<'a, (char, usize)> {
+        Parser::new(move |input: &'a [char]| {
+            let (c, r) = self.0(input)?;
+            let start = consumed(input, r);
+            let repeated = input[start..]
+                .iter()
+                .take_while(|next| same_char(**next, c))
+                .count();
+            Some(((c, repeated + 1), rest(input, start + repeated)))
+        })
+    }
 }
 
-pub fn char<'a>(c: char) -> StringParser<'a, char> {
-    Parser::new(move |input: &[char]| match input.split_at_checked(1) {
-        Some((p, r)) if !p.is_empty() && p[0] == c && !r.is_empty() => Some((p[0], Some(r))),
-        Some((p, r)) if !p.is_empty() && p[0] == c && r.is_empty() => Some((p[0], None)),
-        _ => None,
-    })
-}
+impl<'a, O: 'a> Parser<'a, u8, O> {
+    /// Repeats `self` over exactly the next `byte_budget` bytes. `self` only ever sees the bytes
+    /// inside of the budget, and the parser fails when the matches don't add up to exactly the
+    /// budget. The input after the budget is the remainder
+    pub fn many_within(self, byte_budget: usize) -> Many<'a, u8, O> {
+        Parser::new(move |input: &'a [u8]| {
+            let Some(window) = input.get(..byte_budget) else {
+                return fail_eof(input);
+            };
+            let mut elements = vec![];
+            let mut r = Some(window).filter(|window| !window.is_empty());
+            while let Some(window) = r {
+                let (p, next) = self.0(window)?;
+                if !progressed(window, next) {
+                    return fail(window);
+                }
+                elements.push(p);
+                r = next;
+            }
+            if byte_budget == 0 {
+                return Some((elements, Some(input)));
+            }
+            Some((elements, rest(input, byte_budget)))
+        })
+    }
+}
+
+/// Matches exactly the next `n` bytes and returns them as a slice, failing when fewer are left.
+/// With `n == 0` it gives an empty slice and consumes nothing
+pub fn take_bytes<'a>(n: usize) -> Parser<'a, u8, &'a [u8]> {
+    Parser::new(move |input: &'a [u8]| {
+        let Some(bytes) = input.get(..n) else {
+            return fail_eof(input);
+        };
+        if n == 0 {
+            return Some((bytes, Some(input)));
+        }
+        Some((bytes, rest(input, n)))
+    })
+}
+
+pub fn satisfy<'a, F>(f: F) -> StringParser<'a, char>
+where
+    F: Fn(char) -> bool + 'a,
+{
+    Parser::new(move |input: &[char]| match input.first() {
+        Some(c) if accepts(&f, *c) => Some((*c, rest(input, 1))),
+        _ => fail(input),
+    })
+}
+
+/// Returns the next character if it satisfies `f` without consuming it
+pub fn peek_char<'a, F>(f: F) -> StringParser<'a, char>
+where
+    F: Fn(char) -> bool + 'a,
+{
+    Parser::new(move |input: &'a [char]| match input.first() {
+        Some(c) if accepts(&f, *c) => Some((*c, Some(input))),
+        _ => fail(input),
+    })
+}
+
+/// Returns a copy of the next `n` elements without consuming them, or of all that are left when
+/// there are fewer. It never fails
+pub fn peek_n<'a, I: Clone + 'a>(n: usize) -> Parser<'a, I, Vec<I>> {
+    Parser::new(move |input: &'a [I]| Some((input[..n.min(input.len())].to_vec(), Some(input))))
+}
+
+/// Consumes the longest run of characters that satisfy `f` and returns its length, which can be
+/// zero, so it never fails
+pub fn count_while<'a, F>(f: F) -> StringParser<'a, usize>
+where
+    F: Fn(char) -> bool + 'a,
+{
+    Parser::new(move |input: &'a [char]| {
+        let len = input
+            .iter()
+            .position(|c| !accepts(&f, *c))
+            .unwrap_or(input.len());
+        if len == 0 {
+            return Some((0, Some(input)));
+        }
+        Some((len, rest(input, len)))
+    })
+}
+
+/// Same as `satisfy` but labels the parser with `label` for error reporting
+pub fn expect_char<'a, F>(f: F, label: &'static str) -> StringParser<'a, char>
+where
+    F: Fn(char) -> bool + 'a,
+{
+    satisfy(f).label(label)
+}
+
+pub fn char<'a>(c: char) -> StringParser<'a, char> {
+    Parser::new(move |input: &[char]| match input.split_at_checked(1) {
+        Some((p, r)) if !p.is_empty() && same_char(p[0], c) && !r.is_empty() => {
+            Some((p[0], Some(r)))
+        }
+        Some((p, r)) if !p.is_empty() && same_char(p[0], c) && r.is_empty() => Some((p[0], None)),
+        _ => fail(input),
+    })
+}
+
+pub fn digit<'a>() -> StringParser<'a, char> {
+    Parser::new(move |input: &[char]| match input.split_at_checked(1) {
+        Some((p, r)) if !p.is_empty() && p[0].is_ascii_digit() && !r.is_empty() => {
+            Some((p[0], Some(r)))
+        }
+        Some((p, r)) if !p.is_empty() && p[0].is_ascii_digit() && r.is_empty() => {
+            Some((p[0], None))
+        }
+        _ => fail(input),
+    })
+}
+
+/// Parses every line of `input` with `parser` and yields its value, `None` for the lines it
+/// doesn't match. Lines end at `\n` (a `\r` right before it is dropped) like `str::lines`. Lines
+/// are slices of `input`, so the only conversion is the one of the text into chars done once by
+/// the caller instead of one `Vec<char>` per line
+pub fn parse_lines<'p, 'a, O: 'a>(
+    parser: &'p Parser<'a, char, O>,
+    input: &'a [char],
+) -> impl Iterator<Item = Option<O>> + use<'p, 'a, O> {
+    let body = input.strip_suffix(&['\n']).unwrap_or(input);
+    (!input.is_empty())
+        .then(|| body.split(|c| *c == '\n'))
+        .into_iter()
+        .flatten()
+        .map(move |line| {
+            let line = line.strip_suffix(&['\r']).unwrap_or(line);
+            parser.parse(line).map(|(o, _)| o)
+        })
+}
+
+/// Splits the whole input into tokens: skips any `whitespace` and then matches a `token`, until
+/// the input ends. Every token comes with its span in `input`. Fails with the error of the first
+/// position where no token matches (or a token matches without consuming anything), offsets
+/// being relative to `input`
+pub fn lex<'a, T: 'a, W: 'a>(
+    token: StringParser<'a, T>,
+    whitespace: StringParser<'a, W>,
+    input: &'a [char],
+) -> Result<Vec<(T, Range<usize>)>, ParseError> {
+    let whitespace = whitespace.many();
+    in_run(input, || {
+        let mut tokens = vec![];
+        let mut start = 0;
+        loop {
+            if let Some((_, r)) = whitespace.0(&input[start..]) {
+                start += consumed(&input[start..], r);
+            }
+            if start == input.len() {
+                return Ok(tokens);
+            }
+            let rest = &input[start..];
+            let (t, r) = token.parse_with_error(rest).map_err(|error| ParseError {
+                offset: start + error.offset,
+                ..error
+            })?;
+            let end = start + consumed(rest, r);
+            if end == start {
+                return Err(ParseError {
+                    offset: start,
+                    expected: vec![],
+                    context: vec![],
+                    snippet: None,
+                });
+            }
+            tokens.push((t, start..end));
+            start = end;
+        }
+    })
+}
+
+/// Matches one or more ascii digits as a `u64` in a single pass without allocating, failing when
+/// the number doesn't fit
+pub fn fast_uint<'a>() -> StringParser<'a, u64> {
+    digits_radix(10)
+}
+
+/// Same as `fast_uint` for digits in base `radix`, letters are digits above 9 in either case
+///
+/// # Panics
+/// Panics if `radix` isn't in `2..=36`
+pub fn digits_radix<'a>(radix: u32) -> StringParser<'a, u64> {
+    assert!((2..=36).contains(&radix), "radix {radix} isn't in 2..=36");
+    Parser::new(move |input: &'a [char]| {
+        let mut n: u64 = 0;
+        let mut len = 0;
+        while let Some(d) = input.get(len).and_then(|c| c.to_digit(radix)) {
+            let Some(next) = n
+                .checked_mul(radix as u64)
+                .and_then(|n| n.checked_add(d as u64))
+            else {
+                return fail(input);
+            };
+            n = next;
+            len += 1;
+        }
+        if len == 0 {
+            return fail(input);
+        }
+        Some((n, rest(input, len)))
+    })
+}
+
+/// Matches the string `s` character by character
+pub fn string<'a>(s: &'a str) -> StringParser<'a, String> {
+    let expected: Vec<char> = s.chars().collect();
+    Parser::new(move |input: &'a [char]| literal(&expected, input).map(|r| (s.to_string(), r)))
+}
+
+/// Same as `string` but returns `s` itself instead of allocating a `String`
+pub fn string_static<'a>(s: &'static str) -> StringParser<'a, &'static str> {
+    let expected: Vec<char> = s.chars().collect();
+    Parser::new(move |input: &'a [char]| literal(&expected, input).map(|r| (s, r)))
+}
+
+/// Whether `input` starts with `prefix`, see `same_char`
+fn has_prefix(input: &[char], prefix: &[char]) -> bool {
+    input.len() >= prefix.len() && input.iter().zip(prefix).all(|(a, b)| same_char(*a, *b))
+}
+
+/// Matches `expected` at the start of `input` and returns the remainder after it
+fn literal<'a>(expected: &[char], input: &'a [char]) -> Option<Option<&'a [char]>> {
+    for (i, c) in expected.iter().enumerate() {
+        match input.get(i) {
+            Some(got) if same_char(*got, *c) => {}
+            Some(_) => return fail(&input[i..]),
+            None => return fail_eof(input),
+        }
+    }
+    if expected.is_empty() {
+        return Some(Some(input));
+    }
+    Some(rest(input, expected.len()))
+}
+
+/// Matches a single extended grapheme cluster (what a user sees as one character), as defined by
+/// Unicode and implemented by the `unicode-segmentation` crate, and returns it
+#[cfg(feature = "grapheme")]
+pub fn grapheme<'a>() -> StringParser<'a, String> {
+    Parser::new(move |input: &'a [char]| {
+        let len = grapheme_len(input);
+        if len == 0 {
+            return fail(input);
+        }
+        Some((input[..len].iter().collect(), rest(input, len)))
+    })
+}
+
+/// Matches a single grapheme cluster (see `grapheme`) that satisfies `f`
+#[cfg(feature = "grapheme")]
+pub fn grapheme_satisfy<'a, F>(f: F) -> StringParser<'a, String>
+where
+    F: Fn(&str) -> bool + 'a,
+{
+    grapheme().filter(move |g| f(g))
+}
+
+/// Number of chars of the grapheme cluster at the start of `input`. Only a window of the input is
+/// segmented, it grows until the cluster ends inside of it
+#[cfg(feature = "grapheme")]
+fn grapheme_len(input: &[char]) -> usize {
+    use unicode_segmentation::UnicodeSegmentation;
+
+    let mut window = 8;
+    loop {
+        let text: String = input.iter().take(window).collect();
+        let len = text.graphemes(true).next().map_or(0, |g| g.chars().count());
+        if len < window || window >= input.len() {
+            return len;
+        }
+        window *= 2;
+    }
+}
+
+/// Matches the literal `prefix` followed by `body` and keeps only the value of `body`, like `0x`
+/// in front of hex digits
+pub fn strip_prefix<'a, O: 'a>(prefix: &'a str, body: StringParser<'a, O>) -> StringParser<'a, O> {
+    string(prefix).and(body).map(|(_, o)| o)
+}
+
+/// Matches the whole word `s`, so it fails when `s` is directly followed by an alphanumeric
+/// character or `_`. `word("in")` matches `in x` but not `index`
+pub fn word<'a>(s: &'a str) -> StringParser<'a, String> {
+    let literal = string(s);
+    Parser::new(move |input: &'a [char]| match literal.0(input)? {
+        (_, Some([c, ..])) if c.is_alphanumeric() || *c == '_' => fail(input),
+        matched => Some(matched),
+    })
+}
+
+/// Matches the longest keyword from `table` and returns its value, so `return` isn't shadowed by
+/// `ret`. Keywords of the same length are tried in the order of the table
+pub fn keywords<'a, V: Clone + 'a>(table: Vec<(&'a str, V)>) -> StringParser<'a, V> {
+    let mut table: Vec<(Vec<char>, V)> = table
+        .into_iter()
+        .map(|(keyword, value)| (keyword.chars().collect(), value))
+        .collect();
+    table.sort_by_key(|(keyword, _)| std::cmp::Reverse(keyword.len()));
+    Parser::new(move |input: &'a [char]| {
+        match table.iter().find(|(keyword, _)| has_prefix(input, keyword)) {
+            Some((keyword, value)) => Some((value.clone(), rest(input, keyword.len()))),
+            None => fail(input),
+        }
+    })
+}
+
+/// Matches the first of `options` that applies and returns it. With overlapping options like `<`
+/// and `<=` the order matters, use `any_of_longest` to always get the longest one
+pub fn any_of<'a>(options: &'a [&'a str]) -> StringParser<'a, String> {
+    let table: Vec<(Vec<char>, &str)> = options
+        .iter()
+        .map(|option| (option.chars().collect(), *option))
+        .collect();
+    Parser::new(move |input: &'a [char]| {
+        match table.iter().find(|(option, _)| has_prefix(input, option)) {
+            Some((option, s)) => Some((s.to_string(), rest(input, option.len()))),
+            None => fail(input),
+        }
+    })
+}
+
+/// Same as `any_of` but matches the longest of `options` that applies
+pub fn any_of_longest<'a>(options: &'a [&'a str]) -> StringParser<'a, String> {
+    keywords(
+        options
+            .iter()
+            .map(|option| (*option, option.to_string()))
+            .collect(),
+    )
+}
+
+/// Matches the longest of the exact spellings in `variants` and returns it, like
+/// `one_of_strings_exact(&["GET", "Get"])`. Same as `any_of_longest` but it stays case sensitive
+/// inside of `Parser::case_insensitive`
+pub fn one_of_strings_exact<'a>(variants: &'a [&'a str]) -> StringParser<'a, String> {
+    let mut table: Vec<(Vec<char>, &str)> = variants
+        .iter()
+        .map(|variant| (variant.chars().collect(), *variant))
+        .collect();
+    table.sort_by_key(|(variant, _)| std::cmp::Reverse(variant.len()));
+    Parser::new(move |input: &'a [char]| {
+        match table.iter().find(|(variant, _)| input.starts_with(variant)) {
+            Some((variant, s)) => Some((s.to_string(), rest(input, variant.len()))),
+            None => fail(input),
+        }
+    })
+}
+
+/// Matches a single character from a class like `"a-z0-9_"`. The spec is made of ranges (`a-z`)
+/// and single characters, a literal `-` has to be the first or the last character of the spec.
+///
+/// # Panics
+/// Panics if the spec is empty or contains a reversed range like `z-a`
+pub fn char_class<'a>(spec: &'a str) -> StringParser<'a, char> {
+    let spec: Vec<char> = spec.chars().collect();
+    assert!(!spec.is_empty(), "empty character class");
+    let mut ranges = vec![];
+    let mut i = 0;
+    while i < spec.len() {
+        if spec.get(i + 1) == Some(&'-') && i + 2 < spec.len() {
+            let (lo, hi) = (spec[i], spec[i + 2]);
+            assert!(lo <= hi, "reversed range `{lo}-{hi}` in character class");
+            ranges.push((lo, hi));
+            i += 3;
+        } else {
+            ranges.push((spec[i], spec[i]));
+            i += 1;
+        }
+    }
+    satisfy(move |c| ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c)))
+}
+
+/// Parses statements that are each followed by `sync` (the last one can end the input instead)
+/// until the input ends. A statement that fails, or isn't followed by `sync`, gives the
+/// `ParseError` of the attempt (offsets relative to the start of the input given to `parse`) and
+/// the input is skipped up to and including the next `sync` match, or to the end without one.
+/// A `sync` match that ends where the statement started is passed over, so the skip always makes
+/// progress. Successes and errors are kept in source order, so this never fails
+pub fn statements<'a, S: 'a>(
+    statement: Parser<'a, char, S>,
+    sync: Skip<'a, char>,
+) -> StringParser<'a, Vec<Result<S, ParseError>>> {
+    let sync = Rc::new(sync);
+    let separator = sync.clone();
+    let terminated = move |input: &'a [char]| match statement.0(input)? {
+        (s, Some(r)) if !r.is_empty() => match separator.0(r) {
+            Some((_, r)) => Some((s, r)),
+            None => fail(r),
+        },
+        (s, _) => Some((s, None)),
+    };
+    Parser::new(move |input: &'a [char]| {
+        let mut parsed = vec![];
+        let mut rest = Some(input);
+        while let Some(input) = rest.filter(|input| !input.is_empty()) {
+            let (result, tracker) = tracking(|| terminated(input));
+            match result {
+                Some((_, r)) if !progressed(input, r) => break,
+                Some((s, r)) => {
+                    parsed.push(Ok(s));
+                    rest = r;
+                }
+                None => {
+                    let addr = tracker.furthest.unwrap_or(input.as_ptr() as usize);
+                    parsed.push(Err(
+                        tracker.into_error(position(input) + offset_of(input, addr))
+                    ));
+                    rest = (0..input.len())
+                        .find_map(|i| {
+                            sync.0(&input[i..])
+                                .map(|(_, r)| r)
+                                .filter(|r| progressed(input, *r))
+                        })
+                        .flatten();
+                }
+            }
+        }
+        recovered(Some((parsed, rest)))
+    })
+}
+
+/// Matches a line ending and returns it: `\r\n`, `\n` or `\r`, in that order so `\r\n` is a
+/// single ending
+pub fn line_ending<'a>() -> StringParser<'a, String> {
+    any_of(&["\r\n", "\n", "\r"])
+}
+
+/// Splits the whole input into lines at every `line_ending`, like `split_on` except that an
+/// ending at the very end of the input doesn't start another empty line
+pub fn lines<'a>() -> StringParser<'a, Vec<String>> {
+    split_on(line_ending()).map(|mut lines| {
+        if lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        lines
+    })
+}
+
+/// Matches a balanced `open`/`close` region (the input has to start with `open`) and returns the
+/// text in between the outermost pair. Nested pairs are kept as part of the inner text, so
+/// `(a(b)c)` gives `a(b)c`, and the first `close` without a matching `open` ends the region.
+/// When `open` and `close` are the same character the region can't nest and ends at the next
+/// one, so `balanced('"', '"')` matches a quoted string. Fails if the input ends before the
+/// region is balanced
+pub fn balanced<'a>(open: char, close: char) -> StringParser<'a, String> {
+    Parser::new(move |input: &'a [char]| {
+        let (end, _) = balanced_end(input, open, close)?;
+        Some((input[1..end].iter().collect(), rest(input, end + 1)))
+    })
+}
+
+/// Same as `balanced`, for extracting the inner text of a block like `{ ... }`
+pub fn balanced_content<'a>(open: char, close: char) -> StringParser<'a, String> {
+    balanced(open, close)
+}
+
+/// Splits the whole input at every match of `delim` and returns the text in between. Every
+/// delimiter ends a segment, so leading, trailing and adjacent delimiters give empty segments:
+/// `/a//b/` gives `["", "a", "", "b", ""]` and the empty input gives `[""]`. Matches of `delim`
+/// that don't consume anything are ignored
+pub fn split_on<'a, S: 'a>(delim: Parser<'a, char, S>) -> StringParser<'a, Vec<String>> {
+    Parser::new(move |input: &'a [char]| {
+        let segments = segments(&delim, input)
+            .into_iter()
+            .map(|segment| segment.iter().collect())
+            .collect();
+        Some((segments, None))
+    })
+}
+
+/// Splits the whole input at every match of `delim` like `split_on` and parses every segment
+/// completely with `field`. An empty segment is parsed as an empty slice, so it only works if
+/// `field` can match nothing. Fails if any segment doesn't match or has input left over
+pub fn fields<'a, S: 'a, F: 'a>(
+    field: Parser<'a, char, F>,
+    delim: Parser<'a, char, S>,
+) -> StringParser<'a, Vec<F>> {
+    Parser::new(move |input: &'a [char]| {
+        let values = segments(&delim, input)
+            .into_iter()
+            .map(|segment| match field.0(segment)? {
+                (o, None) | (o, Some([])) => Some(o),
+                (_, Some(leftover)) => fail(leftover),
+            })
+            .collect::<Option<_>>()?;
+        Some((values, None))
+    })
+}
+
+/// The segments of `input` in between the matches of `delim`, see `split_on`
+fn segments<'a, S>(delim: &Parser<'a, char, S>, input: &'a [char]) -> Vec<&'a [char]> {
+    let mut segments = vec![];
+    let (mut start, mut i) = (0, 0);
+    while i < input.len() {
+        match delim.0(&input[i..]) {
+            Some((_, r)) if progressed(&input[i..], r) => {
+                segments.push(&input[start..i]);
+                i += consumed(&input[i..], r);
+                start = i;
+            }
+            _ => i += 1,
+        }
+    }
+    segments.push(&input[start..]);
+    recovered(segments)
+}
+
+/// Matches a balanced `open`/`close` region like `balanced` and returns the deepest nesting
+/// level inside of it, so `((()))` gives 3 and `()()` gives 1 for its first region
+pub fn max_depth<'a>(open: char, close: char) -> StringParser<'a, usize> {
+    Parser::new(move |input: &'a [char]| {
+        let (end, max) = balanced_end(input, open, close)?;
+        Some((max, rest(input, end + 1)))
+    })
+}
+
+/// Returns the index of the `close` that balances the `open` at the start of `input` together
+/// with the deepest nesting level reached before it
+fn balanced_end(input: &[char], open: char, close: char) -> Option<(usize, usize)> {
+    if input.first() != Some(&open) {
+        return fail(input);
+    }
+    if open == close {
+        return match input[1..].iter().position(|c| *c == close) {
+            Some(i) => Some((i + 1, 1)),
+            None => fail_eof(input),
+        };
+    }
+    let (mut depth, mut max) = (0usize, 0);
+    for (i, c) in input.iter().enumerate() {
+        if *c == open {
+            depth += 1;
+            max = max.max(depth);
+        } else if *c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some((i, max));
+            }
+        }
+    }
+    fail_eof(input)
+}
+
+/// A single top level `parse` call, slices inside of it are identified by their addresses
+#[derive(Clone, Copy)]
+struct Run {
+    id: u64,
+    start: usize,
+    end: usize,
+}
+
+thread_local! {
+    static RUN: Cell<Option<Run>> = const { Cell::new(None) };
+    static NEXT_RUN: Cell<u64> = const { Cell::new(0) };
+}
+
+thread_local! {
+    /// Set when a `Parser::expect` failed, every alternation clears it when it starts and checks it
+    /// after an alternative failed. Combinators that turn a failure into a match clear it through
+    /// `recovered`
+    static COMMITTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Forgets a failed `Parser::expect` once a combinator recovered from the failure, so it doesn't
+/// stop the alternations around the match from backtracking
+fn recovered<T>(value: T) -> T {
+    COMMITTED.set(false);
+    value
+}
+
+fn current_run() -> Option<Run> {
+    RUN.get()
+}
+
+/// Offset of the start of `input` in the input of the current run
+fn position<I>(input: &[I]) -> usize {
+    current_run().map_or(0, |run| {
+        (input.as_ptr() as usize).saturating_sub(run.start) / std::mem::size_of::<I>().max(1)
+    })
+}
+
+/// Runs `f` as part of the current run if `input` lies inside of its input, or as a new run
+/// otherwise
+fn in_run<I, T>(input: &[I], f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Run>, bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            RUN.set(self.0);
+            COMMITTED.set(self.1);
+        }
+    }
+
+    let range = input.as_ptr_range();
+    let (start, end) = (range.start as usize, range.end as usize);
+    let outer = RUN.get();
+    if outer.is_some_and(|run| run.start <= start && end <= run.end) {
+        return f();
+    }
+    let id = NEXT_RUN.get();
+    NEXT_RUN.set(id + 1);
+    RUN.set(Some(Run { id, start, end }));
+    let _restore = Restore(outer, COMMITTED.replace(false));
+    f()
+}
+
+#[cfg(feature = "instrument")]
+thread_local! {
+    static STATS: Cell<Option<ParseStats>> = const { Cell::new(None) };
+}
+
+/// Parser invocations of the current `Parser::limited`
+#[cfg(feature = "instrument")]
+#[derive(Clone, Copy)]
+struct Steps {
+    taken: usize,
+    max: usize,
+}
+
+#[cfg(feature = "instrument")]
+thread_local! {
+    static STEPS: Cell<Option<Steps>> = const { Cell::new(None) };
+}
+
+/// Counts an invocation for the current `Parser::limited`, if there is one, and tells whether
+/// it's still within the limit
+#[cfg(feature = "instrument")]
+fn step() -> bool {
+    let Some(mut steps) = STEPS.get() else {
+        return true;
+    };
+    steps.taken += 1;
+    STEPS.set(Some(steps));
+    steps.taken <= steps.max
+}
+
+/// Updates the stats of the current `Parser::parse_with_stats`, if there is one
+#[cfg(feature = "instrument")]
+fn count(f: impl FnOnce(&mut ParseStats)) {
+    if let Some(mut stats) = STATS.get() {
+        f(&mut stats);
+        STATS.set(Some(stats));
+    }
+}
+
+thread_local! {
+    static FOLD_CASE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether the predicate `f` of a char parser accepts `c`, inside of `Parser::case_insensitive`
+/// it's enough for `f` to accept the other case of an ASCII letter. Outside of it `f` is only
+/// called once
+fn accepts(f: impl Fn(char) -> bool, c: char) -> bool {
+    f(c) || FOLD_CASE.get()
+        && c.is_ascii_alphabetic()
+        && f(if c.is_ascii_lowercase() {
+            c.to_ascii_uppercase()
+        } else {
+            c.to_ascii_lowercase()
+        })
+}
+
+/// Whether the char parsers consider `a` and `b` the same character, which ignores ASCII case
+/// inside of `Parser::case_insensitive`
+fn same_char(a: char, b: char) -> bool {
+    a == b || a.eq_ignore_ascii_case(&b) && FOLD_CASE.get()
+}
+
+thread_local! {
+    /// Nodes of the current `Parser::parse_with_tree`, one list of children for every parser
+    /// that is running right now with the roots at the bottom
+    static TREE: RefCell<Option<Vec<Vec<ParseTree>>>> = const { RefCell::new(None) };
+    /// Whether `TREE` holds a tree, so parsers outside of `parse_with_tree` skip borrowing it
+    static RECORDING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f` as a parser tagged `tag` and records it into the current `Parser::parse_with_tree`,
+/// if there is one
+fn node<'a, I, T>(
+    tag: &'static str,
+    input: &'a [I],
+    f: impl FnOnce() -> Option<(T, Option<&'a [I]>)>,
+) -> Option<(T, Option<&'a [I]>)> {
+    if !RECORDING.get() {
+        return f();
+    }
+    TREE.with_borrow_mut(|tree| {
+        if let Some(frames) = tree {
+            frames.push(vec![]);
+        }
+    });
+    let result = f();
+    TREE.with_borrow_mut(|tree| {
+        let Some(frames) = tree else {
+            return;
+        };
+        let children = frames.pop().unwrap_or_default();
+        let outcome = match &result {
+            Some((_, r)) => TraceOutcome::Matched {
+                consumed: consumed(input, *r),
+            },
+            None => TraceOutcome::Failed,
+        };
+        if let Some(parent) = frames.last_mut() {
+            parent.push(ParseTree {
+                tag,
+                offset: position(input),
+                outcome,
+                children,
+            });
+        }
+    });
+    result
+}
+
+/// Failures seen while `Parser::parse_with_error` runs
+#[derive(Default)]
+struct Tracker {
+    /// Address of the furthest failure
+    furthest: Option<usize>,
+    /// Labels of the parsers that failed at `furthest`
+    expected: Vec<&'static str>,
+    /// Contexts that were running at `furthest`
+    context: Vec<&'static str>,
+    /// Contexts that are running right now
+    stack: Vec<&'static str>,
+    /// Input around `furthest`, captured by `Parser::snapshot`
+    snippet: Option<Snippet>,
+    /// Error given by `Parser::map_res` at `furthest`, it replaces the tracked one
+    error: Option<ParseError>,
+}
+
+impl Tracker {
+    fn into_error(self, offset: usize) -> ParseError {
+        match self.error {
+            Some(error) => ParseError {
+                offset,
+                snippet: self.snippet.or(error.snippet),
+                ..error
+            },
+            None => ParseError {
+                offset,
+                expected: self.expected,
+                context: self.context,
+                snippet: self.snippet,
+            },
+        }
+    }
+}
+
+thread_local! {
+    static TRACKER: RefCell<Option<Tracker>> = const { RefCell::new(None) };
+    /// Whether `TRACKER` holds a tracker, so failures outside of `tracking` skip borrowing it
+    static TRACKING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f` with failure tracking enabled and returns what was tracked
+fn tracking<T>(f: impl FnOnce() -> T) -> (T, Tracker) {
+    struct Restore(Option<Option<Tracker>>, bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            if let Some(outer) = self.0.take() {
+                TRACKER.set(outer);
+            }
+            TRACKING.set(self.1);
+        }
+    }
+
+    let mut restore = Restore(
+        Some(TRACKER.replace(Some(Tracker::default()))),
+        TRACKING.replace(true),
+    );
+    let result = f();
+    let tracker = TRACKER
+        .replace(restore.0.take().unwrap())
+        .unwrap_or_default();
+    (result, tracker)
+}
+
+fn record_failure(addr: usize, label: Option<&'static str>) {
+    if !TRACKING.get() {
+        return;
+    }
+    TRACKER.with_borrow_mut(|tracker| {
+        let Some(tracker) = tracker else {
+            return;
+        };
+        if tracker.furthest.is_none_or(|furthest| addr > furthest) {
+            tracker.furthest = Some(addr);
+            tracker.expected.clear();
+            tracker.context.clone_from(&tracker.stack);
+            tracker.snippet = None;
+            tracker.error = None;
+        }
+        if tracker.furthest == Some(addr) {
+            if let Some(label) = label.filter(|label| !tracker.expected.contains(label)) {
+                tracker.expected.push(label);
+            }
+        }
+    });
+}
+
+/// Records a failure with a custom error, the contexts that are running right now are put in
+/// front of its context
+fn record_error(addr: usize, error: ParseError) {
+    record_failure(addr, None);
+    TRACKER.with_borrow_mut(|tracker| {
+        let Some(tracker) = tracker.as_mut().filter(|t| t.furthest == Some(addr)) else {
+            return;
+        };
+        let mut context = tracker.stack.clone();
+        context.extend(error.context.iter());
+        tracker.error = Some(ParseError { context, ..error });
+    });
+}
+
+/// Records what an inner `tracking` call tracked as failures of the current one
+fn forward(inner: &Tracker) {
+    let Some(addr) = inner.furthest else {
+        return;
+    };
+    if let Some(error) = &inner.error {
+        return record_error(addr, error.clone());
+    }
+    TRACKER.with_borrow_mut(|tracker| {
+        let Some(tracker) = tracker else {
+            return;
+        };
+        if tracker.furthest.is_none_or(|furthest| addr > furthest) {
+            tracker.furthest = Some(addr);
+            tracker.expected.clear();
+            tracker.context = [&tracker.stack[..], &inner.context[..]].concat();
+            tracker.snippet.clone_from(&inner.snippet);
+            tracker.error = None;
+        }
+        if tracker.furthest == Some(addr) {
+            for label in &inner.expected {
+                if !tracker.expected.contains(label) {
+                    tracker.expected.push(label);
+                }
+            }
+        }
+    });
+}
+
+/// Records a failure at the start of `input`
+fn fail<I, T>(input: &[I]) -> Option<T> {
+    record_failure(input.as_ptr() as usize, None);
+    None
+}
+
+/// Records a failure at the end of `input`
+fn fail_eof<I, T>(input: &[I]) -> Option<T> {
+    record_failure(input.as_ptr_range().end as usize, None);
+    None
+}
+
+/// Converts the address of an element into its offset in `input`
+fn offset_of<I>(input: &[I], addr: usize) -> usize {
+    addr.saturating_sub(input.as_ptr() as usize) / std::mem::size_of::<I>().max(1)
+}
+
+/// How many elements of `input` a parser that returned the remainder `r` consumed
+fn consumed<I>(input: &[I], r: Option<&[I]>) -> usize {
+    input.len() - r.map_or(0, |r| r.len())
+}
+
+/// Whether a parser that returned the remainder `r` consumed anything from `input`
+fn progressed<I>(input: &[I], r: Option<&[I]>) -> bool {
+    consumed(input, r) > 0
+}
+
+/// Builds the remainder after `consumed` elements, `None` when everything was consumed
+fn rest<I>(input: &[I], consumed: usize) -> Option<&[I]> {
+    match &input[consumed..] {
+        [] => None,
+        r => Some(r),
+    }
+}
+
+/// Runs the parsers in order and calls the constructor with all of their values, failing if any
+/// of them fails: `seq!(ident, char('='), expr => |name, _, value| Assign { name, value })`
+#[macro_export]
+macro_rules! seq {
+    ($($parser:expr),+ $(,)? => $constructor:expr) => {
+        $crate::seq!(@bind [] $($parser),+ => $constructor)
+    };
+    (@bind [$($value:ident = $bound:expr),*] $parser:expr $(, $rest:expr)* => $constructor:expr) => {
+        $crate::seq!(@bind [$($value = $bound,)* value = $parser] $($rest),* => $constructor)
+    };
+    (@bind [$($value:ident = $parser:expr),*] => $constructor:expr) => {{
+        $(let $value = $parser;)*
+        let constructor = $constructor;
+        $crate::Parser::new(move |input| {
+            let rest = Some(input);
+            $(let ($value, rest) = $value.parse(rest?)?;)*
+            Some((constructor($($value),*), rest))
+        })
+    }};
+}
+
+/// Tries the parsers in order like `Parser::choice` and wraps the value of the one that matched
+/// with its constructor: `tagged_choice!(number => Expr::Num, ident => Expr::Var)`
+#[macro_export]
+macro_rules! tagged_choice {
+    ($($parser:expr => $constructor:expr),+ $(,)?) => {
+        $crate::Parser::choice(vec![$($crate::Parser::map($parser, $constructor)),+])
+    };
+}
+
+pub trait CollectChars {
+    #[allow(clippy::wrong_self_convention)]
+    fn into_string(&self) -> String;
+}
+
+impl CollectChars for char {
+    fn into_string(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl CollectChars for Vec<char> {
+    fn into_string(&self) -> String {
+        self.iter().collect()
+    }
+}
+
+impl<T: CollectChars> CollectChars for Option<T> {
+    fn into_string(&self) -> String {
+        self.as_ref().map(|t| t.into_string()).unwrap_or_default()
+    }
+}
+
+impl<A: CollectChars, B: CollectChars> CollectChars for (A, B) {
+    fn into_string(&self) -> String {
+        let mut s = String::new();
+        s.push_str(&self.0.into_string());
+        s.push_str(&self.1.into_string());
+        s
+    }
+}
+
+impl<'a, I: 'a, O: CollectChars + 'a> Parser<'a, I, O> {
+    pub fn into_string(self) -> Parser<'a, I, String> {
+        self.map(|o| o.into_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn single_char() {
+        let c_parser = char('c').into_string();
+        assert_eq!(c_parser.parse(&['c']).unwrap(), ("c".to_string(), None))
+    }
+    #[test]
+    fn single_digit() {
+        let digit_parser = digit().into_string();
+        assert_eq!(digit_parser.parse(&['1']).unwrap(), ("1".to_string(), None))
+    }
+    #[test]
+    fn or() {
+        let c_parser = char('c');
+        let digit_parser = digit();
+        let c_or_digit_parser = c_parser.or(digit_parser).into_string();
+        assert_eq!(
+            c_or_digit_parser.parse(&['c']),
+            Some(("c".to_string(), None))
+        );
+        assert_eq!(
+            c_or_digit_parser.parse(&['1']),
+            Some(("1".to_string(), None))
+        );
+        assert_eq!(c_or_digit_parser.parse(&['a']), None);
+    }
+
+    #[test]
+    fn and() {
+        let c_parser = char('c');
+        let d_parser = char('d');
+        let c_and_d_parser = c_parser.and(d_parser).into_string();
+        let c: &[char] = &['c'];
+        assert_eq!(
+            c_and_d_parser.parse(&['c', 'd']),
+            Some(("cd".to_string(), None))
+        );
+        assert_eq!(c_and_d_parser.parse(&['c']), None);
+        assert_eq!(c_and_d_parser.parse(&['c', 'c']), None);
+        assert_eq!(
+            c_and_d_parser.parse(&['c', 'd', 'c']),
+            Some(("cd".to_string(), Some(c)))
+        );
+        assert_eq!(c_and_d_parser.parse(&['a']), None);
+    }
+
+    #[test]
+    fn then_maybe() {
+        let c_parser = char('c');
+        let d_parser = char('d');
+        let c: &[char] = &['c'];
+        let c_and_then_maybe_d_parser = c_parser.then_maybe(d_parser).into_string();
+        assert_eq!(
+            c_and_then_maybe_d_parser.parse(&['c', 'd']),
+            Some(("cd".to_string(), None))
+        );
+        assert_eq!(
+            c_and_then_maybe_d_parser.parse(&['c', 'c']),
+            Some(("c".to_string(), Some(c)))
+        );
+        assert_eq!(
+            c_and_then_maybe_d_parser.parse(&['c', 'd', 'c']),
+            Some(("cd".to_string(), Some(c)))
+        );
+        assert_eq!(c_and_then_maybe_d_parser.parse(&['d', 'c']), None);
+    }
+    #[test]
+    fn many() {
+        let many_c_parser = char('c').many().into_string();
+        let d: &[char] = &['d'];
+        assert_eq!(
+            many_c_parser.parse(&['c', 'c', 'c']),
+            Some(("ccc".to_string(), None))
+        );
+        assert_eq!(
+            many_c_parser.parse(&['c', 'c', 'd']),
+            Some(("cc".to_string(), Some(d)))
+        );
+        assert_eq!(
+            many_c_parser.parse(&['c', 'd']),
+            Some(("c".to_string(), Some(d)))
+        );
+    }
+
+    #[test]
+    fn many1() {
+        let many1_c_parser = char('c').many1().into_string();
+        assert_eq!(
+            many1_c_parser.parse(&['c', 'c', 'c']),
+            Some(("ccc".to_string(), None))
+        );
+        assert_eq!(many1_c_parser.parse(&['d']), None);
+    }
+
+    #[test]
+    fn identifier() {
+        let ident_parser = satisfy(|c: char| c.is_alphabetic() || c == '_')
+            .then_maybe(satisfy(|c: char| c.is_alphanumeric() || c == '_').many())
+            .into_string();
+
+        assert_eq!(
+            ident_parser.parse(&['h', 'e', 'l', 'l', 'o', '_', 'w', 'o', 'r', 'l', 'd']),
+            Some(("hello_world".to_string(), None))
+        );
+    }
+
+    #[test]
+    fn balanced_parens() {
+        let nested = chars("(a(b)c)");
+        let empty = chars("()x");
+        let unbalanced = chars("(a(b)");
+        let unopened = chars("a(b)");
+        let parens = balanced('(', ')');
+        let rest: &[char] = &['x'];
+        assert_eq!(parens.parse(&nested), Some(("a(b)c".to_string(), None)));
+        assert_eq!(parens.parse(&empty), Some(("".to_string(), Some(rest))));
+        assert_eq!(parens.parse(&unbalanced), None);
+        assert_eq!(parens.parse(&unopened), None);
+
+        let block = chars("{ if x { y } }; z }");
+        let braces = balanced('{', '}');
+        assert_eq!(
+            braces.parse(&block),
+            Some((" if x { y } ".to_string(), Some(&block[14..])))
+        );
+        assert_eq!(braces.parse(&block[..13]), None);
+        assert_eq!(
+            super::balanced_content('{', '}').parse(&block),
+            braces.parse(&block)
+        );
+
+        let quoted = chars("\"a(b\" c\"");
+        let quotes = balanced('"', '"');
+        assert_eq!(
+            quotes.parse(&quoted),
+            Some(("a(b".to_string(), Some(&quoted[5..])))
+        );
+        assert_eq!(quotes.parse(&quoted[..4]), None);
+    }
+
+    #[test]
+    fn or3_or4() {
+        let abc = char('a').or3(char('b'), char('c')).into_string();
+        assert_eq!(abc.parse(&['c']), Some(("c".to_string(), None)));
+        assert_eq!(abc.parse(&['d']), None);
+
+        let first_wins = char('a').map(|_| 1).or4(
+            char('a').map(|_| 2),
+            char('b').map(|_| 3),
+            digit().map(|_| 4),
+        );
+        assert_eq!(first_wins.parse(&['a']), Some((1, None)));
+        assert_eq!(first_wins.parse(&['7']), Some((4, None)));
+        assert_eq!(first_wins.parse(&['x']), None);
+    }
+
+    #[test]
+    fn parse_all() {
+        let many_c_parser = char('c').many().into_string();
+        let d: &[char] = &['d'];
+        assert_eq!(many_c_parser.parse_all(&['c', 'c']), Ok("cc".to_string()));
+        assert_eq!(many_c_parser.parse_all(&[]), Ok("".to_string()));
+        assert_eq!(
+            many_c_parser.parse_all(&['c', 'd']),
+            Err(TrailingInput::Trailing(d))
+        );
+        assert_eq!(char('c').parse_all(&['d']), Err(TrailingInput::NoMatch));
+
+        let input = chars("a12");
+        let digits = char('a').and(digit().many().spanned());
+        let span = digits.parse_all(&input).map(|(_, d)| (d.start, d.end));
+        assert_eq!(span, Ok((1, 3)));
+    }
+
+    #[test]
+    fn many_indexed() {
+        let indexed = char('c').or(char('d')).many_indexed();
+        let e: &[char] = &['e'];
+        assert_eq!(
+            indexed.parse(&['c', 'd', 'e']),
+            Some((vec![(0, 'c'), (1, 'd')], Some(e)))
+        );
+        assert_eq!(indexed.parse(&[]), Some((vec![], Some(&[][..]))));
+
+        let zero_width = char('c').maybe().many_indexed();
+        assert_eq!(zero_width.parse(&['d']), Some((vec![], Some(&['d'][..]))));
+    }
+
+    #[test]
+    fn expect_eof() {
+        let many_c_parser = char('c').many().into_string().expect_eof();
+        assert_eq!(
+            many_c_parser.parse(&['c', 'c']),
+            Some(("cc".to_string(), None))
+        );
+        assert_eq!(many_c_parser.parse(&[]), Some(("".to_string(), None)));
+        assert_eq!(many_c_parser.parse(&['c', 'd']), None);
+
+        let c_eof = char('c').expect_eof().maybe();
+        assert_eq!(c_eof.parse(&['c']), Some((Some('c'), None)));
+    }
+
+    #[test]
+    fn char_class() {
+        let ident = super::char_class("a-z0-9_").many().into_string();
+        let rest: &[char] = &['-', 'A'];
+        assert_eq!(
+            ident.parse(&['a', '1', '_', 'z', '-', 'A']),
+            Some(("a1_z".to_string(), Some(rest)))
+        );
+
+        let sign = super::char_class("+-");
+        assert_eq!(sign.parse(&['-']), Some(('-', None)));
+        assert_eq!(sign.parse(&['*']), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn char_class_reversed_range() {
+        super::char_class("z-a");
+    }
+
+    #[test]
+    fn then_skip() {
+        let input = chars("42;");
+        let missing = chars("42");
+        let statement = digit().many().into_string().then_skip(char(';'));
+        assert_eq!(statement.parse(&input), Some(("42".to_string(), None)));
+        assert_eq!(statement.parse(&missing), None);
+    }
+
+    #[test]
+    fn collect() {
+        let input = chars("abc1");
+        let letters: Parser<char, String> = satisfy(|c| c.is_alphabetic()).collect();
+        let rest: &[char] = &['1'];
+        assert_eq!(letters.parse(&input), Some(("abc".to_string(), Some(rest))));
+        assert_eq!(letters.parse(&[]), Some(("".to_string(), Some(&[][..]))));
+
+        let unique: Parser<char, std::collections::HashSet<char>> =
+            char('a').or(char('b')).collect();
+        let (set, rest) = unique.parse(&['a', 'b', 'a']).unwrap();
+        assert_eq!(set, ['a', 'b'].into_iter().collect());
+        assert_eq!(rest, None);
+    }
+
+    #[test]
+    fn sep_by_collect() {
+        use std::collections::HashMap;
+        let input = chars("a=1;b=2;c=3");
+        let trailing = chars("a=1;");
+        let pairs: Parser<char, HashMap<char, char>> = satisfy(|c| c.is_alphabetic())
+            .then_skip(char('='))
+            .and(digit())
+            .sep_by_collect(char(';'));
+        let (map, rest) = pairs.parse(&input).unwrap();
+        assert_eq!(map, HashMap::from([('a', '1'), ('b', '2'), ('c', '3')]));
+        assert_eq!(rest, None);
+
+        let semicolon: &[char] = &[';'];
+        let (map, rest) = pairs.parse(&trailing).unwrap();
+        assert_eq!(map, HashMap::from([('a', '1')]));
+        assert_eq!(rest, Some(semicolon));
+
+        assert_eq!(pairs.parse(&[]), Some((HashMap::new(), Some(&[][..]))));
+    }
+
+    #[test]
+    fn and_ws() {
+        let spaced = chars("c \t d ");
+        let tight = chars("cd");
+        let c_and_d_parser = char('c').and_ws(char('d')).into_string();
+        let space: &[char] = &[' '];
+        assert_eq!(
+            c_and_d_parser.parse(&spaced),
+            Some(("cd".to_string(), Some(space)))
+        );
+        assert_eq!(c_and_d_parser.parse(&tight), Some(("cd".to_string(), None)));
+        assert_eq!(c_and_d_parser.parse(&['c', ' ']), None);
+    }
+
+    #[test]
+    fn unless() {
+        let keyword = chars("if");
+        let name = chars("ix");
+        let ident = satisfy(|c| c.is_alphabetic())
+            .many()
+            .into_string()
+            .unless(char('i').and(char('f')));
+        assert_eq!(ident.parse(&keyword), None);
+        assert_eq!(ident.parse(&name), Some(("ix".to_string(), None)));
+    }
+
+    #[test]
+    fn many1_till() {
+        let quoted = chars("\"abc\"x");
+        let empty = chars("\"\"");
+        let unterminated = chars("\"abc");
+        let string = char('"')
+            .and(satisfy(|_| true).many1_till(char('"')))
+            .map(|(_, (body, _))| body.into_iter().collect::<String>());
+        let x: &[char] = &['x'];
+        assert_eq!(string.parse(&quoted), Some(("abc".to_string(), Some(x))));
+        assert_eq!(string.parse(&empty), None);
+        assert_eq!(string.parse(&unterminated), None);
+    }
+
+    #[test]
+    fn cache_failures() {
+        let input = chars("b");
+        let calls = Rc::new(Cell::new(0));
+        let counted = calls.clone();
+        let expensive = Parser::new(move |input: &[char]| {
+            counted.set(counted.get() + 1);
+            char('a').parse(input)
+        })
+        .cache_failures();
+        let twice =
+            Parser::new(move |input| expensive.parse(input).or_else(|| expensive.parse(input)));
+
+        assert_eq!(twice.parse(&input), None);
+        assert_eq!(calls.get(), 1);
+        assert_eq!(twice.parse(&input), None);
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn string() {
+        let input = chars("foobar");
+        let short = chars("fo");
+        let foo = super::string("foo");
+        let bar: &[char] = &['b', 'a', 'r'];
+        assert_eq!(foo.parse(&input), Some(("foo".to_string(), Some(bar))));
+        assert_eq!(foo.parse(bar), None);
+        assert_eq!(foo.parse(&short), None);
+        assert_eq!(
+            super::string("").parse(bar),
+            Some(("".to_string(), Some(bar)))
+        );
+    }
+
+    #[test]
+    fn furthest_error() {
+        let input = chars("fooba");
+        let keywords = Parser::choice(vec![super::string("fox"), super::string("foobar")]);
+        assert_eq!(
+            keywords.parse_with_error(&input),
+            Err(ParseError {
+                offset: 5,
+                expected: vec![],
+                context: vec![],
+                snippet: None
+            })
+        );
+
+        let number = chars("12a");
+        let digits = digit().many().into_string().expect_eof();
+        assert_eq!(
+            digits.parse_with_error(&number),
+            Err(ParseError {
+                offset: 2,
+                expected: vec![],
+                context: vec![],
+                snippet: None
+            })
+        );
+        assert_eq!(
+            digits.parse_with_error(&number[..2]),
+            Ok(("12".to_string(), None))
+        );
+    }
+
+    #[test]
+    fn expect_char() {
+        let input = chars("a+x");
+        let sum = satisfy(|c| c.is_alphabetic())
+            .and(char('+'))
+            .and(super::expect_char(|c| c.is_ascii_digit(), "digit").or(char('(').label("group")));
+        let error = sum.parse_with_error(&input).unwrap_err();
+        assert_eq!(error.offset, 2);
+        assert_eq!(error.expected, vec!["digit", "group"]);
+        assert_eq!(
+            error.to_string(),
+            "parse error at offset 2, expected digit or group"
+        );
+    }
+
+    #[test]
+    fn iter_over() {
+        let input = chars("12 3 x");
+        let mut tokens = digit()
+            .many()
+            .into_string()
+            .then_skip(char(' '))
+            .iter_over(&input);
+        assert_eq!(tokens.next(), Some("12".to_string()));
+        assert_eq!(tokens.next(), Some("3".to_string()));
+        assert_eq!(tokens.next(), None);
+        assert_eq!(tokens.remaining(), Some(&['x'][..]));
+
+        let all: Vec<char> = digit().iter_over(&input[..2]).collect();
+        assert_eq!(all, vec!['1', '2']);
+
+        let starts: Vec<usize> = satisfy(|_| true)
+            .spanned()
+            .iter_over(&input[..3])
+            .map(|s| s.start)
+            .collect();
+        assert_eq!(starts, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn classify() {
+        let many_c_parser = char('c').many().into_string();
+        let d: &[char] = &['d'];
+        assert_eq!(
+            many_c_parser.classify(&['c', 'c']),
+            ParseOutcome::Complete("cc".to_string())
+        );
+        assert_eq!(
+            many_c_parser.classify(&[]),
+            ParseOutcome::Complete("".to_string())
+        );
+        assert_eq!(
+            many_c_parser.classify(&['c', 'd']),
+            ParseOutcome::Partial {
+                value: "c".to_string(),
+                rest: d
+            }
+        );
+        assert_eq!(char('c').classify(&['d']), ParseOutcome::NoMatch);
+    }
+
+    #[test]
+    fn or_value() {
+        let item = digit().or_value('?');
+        let rest: &[char] = &['x'];
+        assert_eq!(item.parse(&['1']), Some(('1', None)));
+        assert_eq!(item.parse(&['x']), Some(('?', Some(rest))));
+    }
+
+    #[cfg(feature = "grapheme")]
+    #[test]
+    fn grapheme() {
+        let flag = chars("\u{1f1fa}\u{1f1e6}!");
+        let accented = chars("e\u{301}x");
+        let family = chars("\u{1f469}\u{200d}\u{1f467}");
+        let joined = chars("a\u{200d}b");
+        let arabic = chars("\u{628}\u{64e}");
+        let devanagari = chars("\u{915}\u{93f}");
+        let hangul = chars("\u{1100}\u{1161}");
+        let marks = chars(&format!("e{}x", "\u{301}".repeat(20)));
+        let g = super::grapheme();
+        let bang: &[char] = &['!'];
+        assert_eq!(
+            g.parse(&flag),
+            Some(("\u{1f1fa}\u{1f1e6}".to_string(), Some(bang)))
+        );
+        assert_eq!(g.parse(&accented).unwrap().0, "e\u{301}");
+        assert_eq!(g.parse(&family), Some((family.iter().collect(), None)));
+        assert_eq!(g.parse(&[]), None);
+
+        assert_eq!(g.parse(&joined).unwrap().0, "a\u{200d}");
+        assert_eq!(g.parse(&arabic), Some((arabic.iter().collect(), None)));
+        assert_eq!(
+            g.parse(&devanagari),
+            Some((devanagari.iter().collect(), None))
+        );
+        assert_eq!(g.parse(&hangul), Some((hangul.iter().collect(), None)));
+        assert_eq!(g.parse(&marks).unwrap().0.chars().count(), 21);
+
+        let e = grapheme_satisfy(|g| g.starts_with('e'));
+        assert_eq!(e.parse(&accented).unwrap().0, "e\u{301}");
+        assert_eq!(e.parse(&flag), None);
+    }
+
+    #[test]
+    fn fold_many_result() {
+        let unique = chars("abc");
+        let duplicated = chars("aba");
+        let letters = satisfy(|c| c.is_alphabetic()).fold_many_result(vec![], |mut seen, c| {
+            if seen.contains(&c) {
+                return Err(());
+            }
+            seen.push(c);
+            Ok(seen)
+        });
+        assert_eq!(letters.parse(&unique), Some((vec!['a', 'b', 'c'], None)));
+        assert_eq!(letters.parse(&duplicated), None);
+        assert_eq!(letters.parse(&[]), Some((vec![], Some(&[][..]))));
+    }
+
+    #[test]
+    fn keywords() {
+        #[derive(Debug, Clone, PartialEq)]
+        enum Token {
+            Ret,
+            Return,
+            If,
+        }
+        let input = chars("return x");
+        let short = chars("ret");
+        let other = chars("else");
+        let keyword = super::keywords(vec![
+            ("ret", Token::Ret),
+            ("return", Token::Return),
+            ("if", Token::If),
+        ]);
+        let rest: &[char] = &[' ', 'x'];
+        assert_eq!(keyword.parse(&input), Some((Token::Return, Some(rest))));
+        assert_eq!(keyword.parse(&short), Some((Token::Ret, None)));
+        assert_eq!(keyword.parse(&other), None);
+    }
+
+    #[test]
+    fn map_slice() {
+        #[derive(Debug, PartialEq)]
+        struct Ident<'a> {
+            name: String,
+            source: &'a [char],
+        }
+        let input = chars("foo_1 = 2");
+        let ident = satisfy(|c| c.is_alphanumeric() || c == '_')
+            .many()
+            .into_string()
+            .map_slice(|name, source| Ident { name, source });
+        let (ident, _) = ident.parse(&input).unwrap();
+        assert_eq!(ident.name, "foo_1");
+        assert_eq!(ident.source, &input[..5]);
+    }
+
+    #[test]
+    fn with_line_count() {
+        let input = chars("a\nb\n\nc");
+        let lines = satisfy(|_| true).many().skip().with_line_count();
+        assert_eq!(lines.parse(&input), Some((((), 3), None)));
+
+        let first_line = satisfy(|c| c != '\n')
+            .many()
+            .into_string()
+            .with_line_count();
+        let rest: &[char] = &input[1..];
+        assert_eq!(
+            first_line.parse(&input),
+            Some((("a".to_string(), 0), Some(rest)))
+        );
+    }
+
+    #[test]
+    fn or_map() {
+        #[derive(Debug, PartialEq)]
+        enum Expr {
+            Num(u32),
+            Var(String),
+        }
+        let number = chars("42");
+        let name = chars("x");
+        let expr = satisfy(|c| c.is_ascii_digit())
+            .many1()
+            .into_string()
+            .or_map(
+                satisfy(|c| c.is_alphabetic()),
+                |digits| Expr::Num(digits.parse().unwrap()),
+                |c| Expr::Var(c.to_string()),
+            );
+        assert_eq!(expr.parse(&number), Some((Expr::Num(42), None)));
+        assert_eq!(expr.parse(&name), Some((Expr::Var("x".to_string()), None)));
+        assert_eq!(expr.parse(&['+']), None);
+    }
+
+    #[test]
+    fn many_within() {
+        let u16_be = || {
+            Parser::new(|input: &[u8]| match input {
+                [hi, lo, r @ ..] => Some((
+                    u16::from_be_bytes([*hi, *lo]),
+                    Some(r).filter(|r| !r.is_empty()),
+                )),
+                _ => None,
+            })
+        };
+        let input = [0, 1, 0, 2, 9];
+        let records = u16_be().many_within(4);
+        let rest: &[u8] = &[9];
+        assert_eq!(records.parse(&input), Some((vec![1, 2], Some(rest))));
+        assert_eq!(u16_be().many_within(3).parse(&input), None);
+        assert_eq!(u16_be().many_within(6).parse(&input), None);
+        assert_eq!(
+            u16_be().many_within(0).parse(&input),
+            Some((vec![], Some(&input[..])))
+        );
+    }
+
+    #[test]
+    fn spanned() {
+        let input = chars("a12x");
+        let number = char('a').and(digit().many().into_string().spanned());
+        let x: &[char] = &['x'];
+        assert_eq!(
+            number.parse(&input),
+            Some((
+                (
+                    'a',
+                    Spanned {
+                        value: "12".to_string(),
+                        start: 1,
+                        end: 3
+                    }
+                ),
+                Some(x)
+            ))
+        );
+    }
+
+    #[test]
+    fn trace() {
+        let input = chars("ab");
+        let sink = Rc::new(RefCell::new(vec![]));
+        let parser = char('x')
+            .trace("x", sink.clone())
+            .or(char('a').trace("a", sink.clone()))
+            .and(char('b').trace("b", sink.clone()))
+            .into_string();
+        assert_eq!(parser.parse(&input), Some(("ab".to_string(), None)));
+        assert_eq!(
+            *sink.borrow(),
+            vec![
+                TraceEvent {
+                    tag: "x",
+                    offset: 0,
+                    outcome: TraceOutcome::Failed
+                },
+                TraceEvent {
+                    tag: "a",
+                    offset: 0,
+                    outcome: TraceOutcome::Matched { consumed: 1 }
+                },
+                TraceEvent {
+                    tag: "b",
+                    offset: 1,
+                    outcome: TraceOutcome::Matched { consumed: 1 }
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn interleave() {
+        let input = chars("1+2+3+");
+        let sum = digit().interleave(char('+'));
+        let plus: &[char] = &['+'];
+        assert_eq!(
+            sum.parse(&input),
+            Some((vec!['1', '+', '2', '+', '3'], Some(plus)))
+        );
+        assert_eq!(sum.parse(&input[..1]), Some((vec!['1'], None)));
+        assert_eq!(sum.parse(plus), None);
+
+        let x = chars("x");
+        let optional = char('a').maybe().interleave(char(',').maybe());
+        assert_eq!(optional.parse(&x), Some((vec![None], Some(&x[..]))));
+    }
+
+    #[test]
+    fn word() {
+        let spaced = chars("in x");
+        let longer = chars("index");
+        let exact = chars("in");
+        let keyword = super::word("in");
+        let rest: &[char] = &[' ', 'x'];
+        assert_eq!(keyword.parse(&spaced), Some(("in".to_string(), Some(rest))));
+        assert_eq!(keyword.parse(&longer), None);
+        assert_eq!(keyword.parse(&exact), Some(("in".to_string(), None)));
+    }
+
+    #[test]
+    fn reparse() {
+        let good = chars("1,2\nx");
+        let bad = chars("1,a\nx");
+        let line = satisfy(|c| c != '\n').many().map_slice(|_, line| line);
+        let fields = line.reparse(digit().interleave(char(',')));
+        let rest: &[char] = &['\n', 'x'];
+        assert_eq!(fields.parse(&good), Some((vec!['1', ',', '2'], Some(rest))));
+        assert_eq!(fields.parse(&bad), None);
+    }
+
+    #[test]
+    fn on_failure() {
+        #[derive(Debug, PartialEq)]
+        enum Item {
+            Number(String),
+            Error,
+        }
+        let input = chars("12;x?;3");
+        let item = digit()
+            .many1()
+            .into_string()
+            .map(Item::Number)
+            .on_failure(|input| {
+                let sync = input.iter().position(|c| *c == ';')?;
+                Some((Item::Error, Some(&input[sync..])))
+            });
+        let items = item.sep_by_collect::<_, Vec<_>>(char(';'));
+        assert_eq!(
+            items.parse(&input),
+            Some((
+                vec![
+                    Item::Number("12".to_string()),
+                    Item::Error,
+                    Item::Number("3".to_string())
+                ],
+                None
+            ))
+        );
+    }
+
+    #[test]
+    fn many_m_n() {
+        let input = chars("1234");
+        let short = chars("1x");
+        let exact = chars("12x");
+        let digits = digit().many_m_n(2, 3).into_string();
+        let x: &[char] = &['x'];
+        assert_eq!(digits.parse(&short), None);
+        assert_eq!(digits.parse(&exact), Some(("12".to_string(), Some(x))));
+        assert_eq!(
+            digits.parse(&input),
+            Some(("123".to_string(), Some(&input[3..])))
+        );
+        assert_eq!(digits.parse(&input[..3]), Some(("123".to_string(), None)));
+    }
+
+    #[test]
+    fn sep_by1_map() {
+        let input = chars("a=1,b=2");
+        let duplicated = chars("a=1,a=2");
+        let pairs = satisfy(|c| c.is_alphabetic())
+            .then_skip(char('='))
+            .and(digit())
+            .sep_by1_map(char(','));
+        assert_eq!(
+            pairs.parse(&input),
+            Some((HashMap::from([('a', '1'), ('b', '2')]), None))
+        );
+        assert_eq!(pairs.parse(&duplicated), None);
+        assert_eq!(pairs.parse(&[]), None);
+    }
+
+    #[test]
+    fn peek_char() {
+        let input = chars("1a");
+        let peek_digit = super::peek_char(|c| c.is_ascii_digit());
+        assert_eq!(peek_digit.parse(&input), Some(('1', Some(&input[..]))));
+        assert_eq!(peek_digit.parse(&input[1..]), None);
+        assert_eq!(peek_digit.parse(&[]), None);
+    }
+
+    #[test]
+    fn many_with() {
+        let input = chars("12345x");
+        let reported = Rc::new(RefCell::new(vec![]));
+        let progress = reported.clone();
+        let digits = digit()
+            .many_with(2, move |count| progress.borrow_mut().push(count))
+            .into_string();
+        let x: &[char] = &['x'];
+        assert_eq!(digits.parse(&input), Some(("12345".to_string(), Some(x))));
+        assert_eq!(*reported.borrow(), vec![2, 4]);
+    }
+
+    #[test]
+    fn string_static() {
+        let input = chars("letx");
+        let short = chars("le");
+        let keyword = super::string_static("let");
+        let x: &[char] = &['x'];
+        assert_eq!(keyword.parse(&input), Some(("let", Some(x))));
+        assert_eq!(keyword.parse(&short), None);
+        assert_eq!(keyword.parse(x), None);
+        assert_eq!(super::string_static("").parse(x), Some(("", Some(x))));
+    }
+
+    #[test]
+    fn map_remainder() {
+        let input = chars("a,b");
+        let skip_comma = char('a').map_remainder(|r| r.and_then(|r| rest(r, 1)));
+        let b: &[char] = &['b'];
+        assert_eq!(skip_comma.parse(&input), Some(('a', Some(b))));
+        assert_eq!(skip_comma.parse(&input[..1]), Some(('a', None)));
+    }
+
+    #[test]
+    fn choice_ref() {
+        let input = chars("a1x");
+        let letter = satisfy(|c| c.is_alphabetic());
+        let number = digit();
+        let letter_first = [&letter, &number];
+        let number_only = [&number];
+        assert_eq!(Parser::choice_ref(&letter_first, &input).unwrap().0, 'a');
+        assert_eq!(
+            Parser::choice_ref(&letter_first, &input[1..]).unwrap().0,
+            '1'
+        );
+        assert_eq!(Parser::choice_ref(&number_only, &input), None);
+        assert_eq!(
+            Parser::choice_ref(&number_only, &input[1..]).unwrap().0,
+            '1'
+        );
+    }
+
+    #[test]
+    fn run() {
+        let input = chars("aaab");
+        let letters = satisfy(|c| c.is_alphabetic()).run();
+        let b: &[char] = &['b'];
+        assert_eq!(letters.parse(&input), Some((('a', 3), Some(b))));
+        assert_eq!(letters.parse(b), Some((('b', 1), None)));
+        assert_eq!(letters.parse(&['1']), None);
+    }
+
+    #[test]
+    fn all_matches() {
+        let input = chars("ab");
+        let ambiguous = Parser::choice(vec![
+            char('a').into_string(),
+            char('x').into_string(),
+            char('a').and(char('b')).into_string(),
+        ])
+        .or(super::string("ab"));
+        let b: &[char] = &['b'];
+        assert_eq!(
+            ambiguous.all_matches(&input),
+            vec![
+                ("a".to_string(), Some(b)),
+                ("ab".to_string(), None),
+                ("ab".to_string(), None)
+            ]
+        );
+        assert_eq!(ambiguous.parse(&input), Some(("a".to_string(), Some(b))));
+        assert_eq!(digit().all_matches(&input), vec![]);
+
+        let lengths = char('a')
+            .into_string()
+            .or(super::string("ab"))
+            .label("prefix")
+            .map(|s| s.len());
+        assert_eq!(lengths.all_matches(&input), vec![(1, Some(b)), (2, None)]);
+    }
+
+    #[test]
+    fn or_empty() {
+        let document = digit().many1().into_string().or_empty("empty".to_string());
+        assert_eq!(
+            document.parse(&[]),
+            Some(("empty".to_string(), Some(&[][..])))
+        );
+        assert_eq!(document.parse(&['1']), Some(("1".to_string(), None)));
+        assert_eq!(document.parse(&['x']), None);
+    }
+
+    #[test]
+    fn seq() {
+        #[derive(Debug, PartialEq)]
+        struct Assign {
+            name: String,
+            value: char,
+        }
+        let input = chars("x=1;");
+        let missing = chars("x=");
+        let assign = seq!(
+            satisfy(|c| c.is_alphabetic()).many1().into_string(),
+            char('='),
+            digit() => |name, _, value| Assign { name, value }
+        );
+        let semicolon: &[char] = &[';'];
+        assert_eq!(
+            assign.parse(&input),
+            Some((
+                Assign {
+                    name: "x".to_string(),
+                    value: '1'
+                },
+                Some(semicolon)
+            ))
+        );
+        assert_eq!(assign.parse(&missing), None);
+        assert_eq!(seq!(digit() => |d| d).parse(&['1']), Some(('1', None)));
+    }
+
+    #[cfg(feature = "instrument")]
+    #[test]
+    fn parse_with_stats() {
+        let input = chars("ac");
+        let ambiguous = char('a').and(char('b')).or(char('a').and(char('c')));
+        let (result, stats) = ambiguous.parse_with_stats(&input);
+        assert_eq!(result, Some((('a', 'c'), None)));
+        assert_eq!(
+            stats,
+            ParseStats {
+                invocations: 7,
+                branches: 2
+            }
+        );
+        assert_eq!(ambiguous.parse_with_stats(&input[1..]).1.invocations, 5);
+    }
+
+    #[test]
+    fn many_backtrack() {
+        let input = chars("ab!c!d");
+        let missing = chars("abc");
+        let greedy = satisfy(|_| true).many_backtrack(char('!'));
+        let d: &[char] = &['d'];
+        assert_eq!(
+            greedy.parse(&input),
+            Some(((vec!['a', 'b', '!', 'c'], '!'), Some(d)))
+        );
+        assert_eq!(greedy.parse(&missing), None);
+        assert_eq!(greedy.parse(&input[2..3]), Some(((vec![], '!'), None)));
+    }
+
+    #[test]
+    fn into_fn() {
+        let input = chars("1x");
+        let digit_fn = digit().into_fn();
+        let digit_or_zero = Parser::new(move |input| digit_fn(input).or(Some(('0', Some(input)))));
+        let x: &[char] = &['x'];
+        assert_eq!(digit_or_zero.parse(&input), Some(('1', Some(x))));
+        assert_eq!(digit_or_zero.parse(x), Some(('0', Some(x))));
+    }
+
+    #[test]
+    fn fast_uint() {
+        let input = chars("1234x");
+        let max = chars("18446744073709551615");
+        let overflow = chars("18446744073709551616");
+        let number = super::fast_uint();
+        let x: &[char] = &['x'];
+        assert_eq!(number.parse(&input), Some((1234, Some(x))));
+        assert_eq!(number.parse(&max), Some((u64::MAX, None)));
+        assert_eq!(number.parse(&overflow), None);
+        assert_eq!(number.parse(x), None);
+    }
+
+    #[test]
+    fn zip() {
+        let input = chars("42;");
+        let short = chars("4x");
+        let number = digit()
+            .many1()
+            .map_slice(|_, slice| slice.len())
+            .zip(super::fast_uint());
+        let semicolon: &[char] = &[';'];
+        assert_eq!(number.parse(&input), Some(((2, 42), Some(semicolon))));
+        let mismatched = digit().zip(super::fast_uint());
+        assert_eq!(mismatched.parse(&input), None);
+        assert_eq!(
+            mismatched.parse(&short),
+            Some((('4', 4), Some(&short[1..])))
+        );
+    }
+
+    #[test]
+    fn context() {
+        let input = chars("[1,[2,x]]");
+        let number = digit().context("number");
+        let inner = char('[')
+            .and(number.sep_by_collect::<_, Vec<_>>(char(',')))
+            .and(char(']'))
+            .context("array");
+        let outer = char('[')
+            .and(digit().and(char(',')))
+            .and(inner)
+            .and(char(']'))
+            .context("document");
+        let error = outer.parse_with_error(&input).unwrap_err();
+        assert_eq!(error.offset, 6);
+        assert_eq!(error.context, vec!["document", "array", "number"]);
+        assert_eq!(
+            error.to_string(),
+            "parse error at offset 6 in document > array > number"
+        );
+    }
+
+    #[test]
+    fn many_dedup() {
+        let input = chars("aa  b   a!");
+        let tokens = satisfy(|c| c.is_whitespace())
+            .map(|_| ' ')
+            .or(satisfy(|c| c.is_alphabetic()))
+            .many_dedup();
+        let bang: &[char] = &['!'];
+        assert_eq!(
+            tokens.parse(&input),
+            Some((vec!['a', ' ', 'b', ' ', 'a'], Some(bang)))
+        );
+        assert_eq!(tokens.parse(bang), Some((vec![], Some(bang))));
+    }
+
+    #[test]
+    fn parse_normalized() {
+        let input = chars("12x");
+        let digits = super::fast_uint();
+        let x: &[char] = &['x'];
+        assert_eq!(digits.parse_normalized(&input), Some((12, x)));
+        let empty: &[char] = &[];
+        assert_eq!(digits.parse_normalized(&input[..2]), Some((12, empty)));
+        assert_eq!(digits.parse_normalized(x), None);
+    }
+
+    #[test]
+    fn many_checked() {
+        let input = chars("ab1");
+        let letters = satisfy(|c| c.is_alphabetic()).many_checked();
+        let one: &[char] = &['1'];
+        assert_eq!(letters.parse(&input), Some((Ok(vec!['a', 'b']), Some(one))));
+        let stalling = char('a').or_value(' ').many_checked();
+        assert_eq!(
+            stalling.parse(&input),
+            Some((Err(Stall { offset: 1 }), Some(&input[1..])))
+        );
+    }
+
+    #[test]
+    fn chain() {
+        let input = chars("3abcd");
+        let prefixed = super::fast_uint().chain(|&len| {
+            satisfy(|_| true)
+                .many_m_n(len as usize, len as usize)
+                .into_string()
+        });
+        let d: &[char] = &['d'];
+        assert_eq!(
+            prefixed.parse(&input),
+            Some(((3, "abc".to_string()), Some(d)))
+        );
+        assert_eq!(prefixed.parse(&input[..1]), None);
+        assert_eq!(prefixed.parse(&input[..3]), None);
+    }
+
+    #[test]
+    fn any_of() {
+        let input = chars("<=1");
+        let options = ["<", "<=", ">"];
+        let one: &[char] = &['1'];
+        assert_eq!(
+            super::any_of(&options).parse(&input),
+            Some(("<".to_string(), Some(&input[1..])))
+        );
+        assert_eq!(
+            any_of_longest(&options).parse(&input),
+            Some(("<=".to_string(), Some(one)))
+        );
+        assert_eq!(super::any_of(&options).parse(one), None);
+        assert_eq!(any_of_longest(&options).parse(one), None);
+    }
+
+    #[test]
+    fn source_text() {
+        let input = chars("3.14;");
+        let float = digit()
+            .many1()
+            .and(char('.'))
+            .and(digit().many1())
+            .into_string()
+            .map(|s| s.parse::<f64>().unwrap());
+        let text = float.source_text();
+        let semicolon: &[char] = &[';'];
+        assert_eq!(
+            text.parse(&input),
+            Some(("3.14".to_string(), Some(semicolon)))
+        );
+    }
+
+    #[test]
+    fn recoverable() {
+        let input = chars("a;?x;b;");
+        let items = satisfy(|c| c.is_alphabetic())
+            .then_skip(char(';'))
+            .recoverable("bad item", char(';').skip())
+            .many();
+        assert_eq!(
+            items.parse(&input),
+            Some((vec![Ok('a'), Err("bad item"), Ok('b')], None))
+        );
+        assert_eq!(
+            items.parse(&input[..4]),
+            Some((vec![Ok('a'), Err("bad item")], None))
+        );
+    }
+
+    #[test]
+    fn many_windowed() {
+        let input = chars("EEiE");
+        let records = satisfy(|c| c.is_alphabetic()).many_windowed(2, |c| *c == 'E');
+        assert_eq!(
+            records.parse(&input),
+            Some((vec![('E', 1), ('E', 2), ('i', 1), ('E', 1)], None))
+        );
+        let none = satisfy(|c| c.is_alphabetic()).many_windowed(0, |c| *c == 'E');
+        assert_eq!(none.parse(&input[..1]), Some((vec![('E', 0)], None)));
+    }
+
+    #[test]
+    fn either() {
+        let (number, name, neither) = (chars("42"), chars("ab"), chars("-"));
+        let ident = satisfy(|c| c.is_alphabetic()).many1().into_string();
+        let atom = super::fast_uint().either(ident);
+        assert_eq!(atom.parse(&number), Some((Either::Left(42), None)));
+        assert_eq!(
+            atom.parse(&name),
+            Some((Either::Right("ab".to_string()), None))
+        );
+        assert_eq!(atom.parse(&neither), None);
+    }
+
+    #[test]
+    fn pad() {
+        let input = chars("# answer\n 42 # done\n");
+        let comment = char('#').and(satisfy(|c| c != '\n').many()).and(char('\n'));
+        let filler = comment.skip().or(satisfy(|c| c.is_whitespace()).skip());
+        let number = super::fast_uint().pad(filler);
+        assert_eq!(number.parse(&input), Some((42, None)));
+        assert_eq!(number.parse(&input[..2]), None);
+    }
+
+    #[test]
+    fn required() {
+        let x = char('x').maybe().required();
+        assert_eq!(x.parse(&['x']), Some(('x', None)));
+        assert_eq!(x.parse(&['y']), None);
+        assert_eq!(x.parse_with_error(&['y']).map_err(|err| err.offset), Err(0));
+    }
+
+    #[test]
+    fn snapshot() {
+        let input = chars("let foo = bar;");
+        let statement = super::string("let foo = ")
+            .and(super::string("baz"))
+            .snapshot(3);
+        let error = statement.parse_with_error(&input).unwrap_err();
+        assert_eq!(error.offset, 12);
+        assert_eq!(error.to_string(), "parse error at offset 12 near ` ba|r;`");
+
+        let start = super::string("x").snapshot(3);
+        let error = start.parse_with_error(&input).unwrap_err();
+        assert_eq!(
+            error.snippet,
+            Some(Snippet {
+                before: String::new(),
+                after: "let".to_string()
+            })
+        );
+        assert_eq!(
+            super::string("x")
+                .parse_with_error(&input)
+                .unwrap_err()
+                .snippet,
+            None
+        );
+    }
+
+    #[test]
+    fn fold_many_right() {
+        let input = chars("abc!");
+        let cons = satisfy(|c| c.is_alphabetic())
+            .fold_many_right("nil".to_string(), |c, acc| format!("({c} {acc})"));
+        assert_eq!(
+            cons.parse(&input),
+            Some(("(a (b (c nil)))".to_string(), Some(&input[3..])))
+        );
+        assert_eq!(
+            cons.parse(&input[3..]),
+            Some(("nil".to_string(), Some(&input[3..])))
+        );
+        let zero_width = super::peek_char(|c| c == 'a').fold_many_right(0, |_, acc| acc + 1);
+        assert_eq!(zero_width.parse(&input), Some((0, Some(&input[..]))));
+    }
+
+    #[test]
+    fn many_spanned() {
+        let input = chars("12 345 6;");
+        let tokens = super::fast_uint()
+            .then_skip(satisfy(|c| c == ' ').many())
+            .many_spanned();
+        assert_eq!(
+            tokens.parse(&input),
+            Some((vec![(12, 0), (345, 3), (6, 7)], Some(&input[8..])))
+        );
+        assert_eq!(
+            tokens.parse(&input[3..]),
+            Some((vec![(345, 0), (6, 4)], Some(&input[8..])))
+        );
+    }
+
+    #[test]
+    fn or_with() {
+        let input = chars("x=1;");
+        let strict = char('x')
+            .and(char('='))
+            .and(super::expect_char(|c| c.is_alphabetic(), "name"))
+            .map(|_| "name");
+        let errors = Rc::new(RefCell::new(vec![]));
+        let seen = errors.clone();
+        let lenient = strict.or_with(move |error| {
+            seen.borrow_mut()
+                .push((error.offset, error.expected.clone()));
+            if error.expected.contains(&"name") {
+                char('x').and(char('=')).and(digit()).map(|_| "number")
+            } else {
+                Parser::new(|input| Some(("incomplete", Some(input))))
+            }
+        });
+        assert_eq!(lenient.parse(&input), Some(("number", Some(&input[3..]))));
+        assert_eq!(
+            lenient.parse(&input[..2]),
+            Some(("incomplete", Some(&input[..2])))
+        );
+        assert_eq!(*errors.borrow(), vec![(2, vec!["name"]), (2, vec![])]);
+    }
 
-pub fn digit<'a>() -> StringParser<'a, char> {
-    Parser::new(move |input: &[char]| match input.split_at_checked(1) {
-        Some((p, r)) if !p.is_empty() && p[0].is_ascii_digit() && !r.is_empty() => {
-            Some((p[0], Some(r)))
+    #[test]
+    fn with_byte_len() {
+        let input = chars("caf\u{e9}\u{1f600} x");
+        let token = satisfy(|c| !c.is_whitespace()).many().with_byte_len();
+        assert_eq!(token.parse(&input).map(|(o, _)| o.1), Some(9));
+        assert_eq!(token.parse(&input[5..]).map(|(o, _)| o.1), Some(0));
+    }
+
+    #[test]
+    fn parse_or_err() {
+        fn assignment(input: &[char]) -> Result<(char, char), ParseError> {
+            let name = satisfy(|c| c.is_alphabetic()).then_skip(char('='));
+            let (name, r) = name.parse_or_err(input)?;
+            let (value, _) = digit().parse_or_err(r.unwrap_or_default())?;
+            Ok((name, value))
         }
-        Some((p, r)) if !p.is_empty() && p[0].is_ascii_digit() && r.is_empty() => {
-            Some((p[0], None))
+        assert_eq!(assignment(&chars("a=1")), Ok(('a', '1')));
+        assert_eq!(assignment(&chars("a=b")).map_err(|err| err.offset), Err(0));
+        assert_eq!(assignment(&chars("1")).map_err(|err| err.offset), Err(0));
+    }
+
+    #[test]
+    fn length_between() {
+        let input = chars("abcdefghijklmnopq");
+        let username = satisfy(|c| c.is_alphanumeric())
+            .many()
+            .source_text()
+            .length_between(3, 16);
+        assert_eq!(username.parse(&input[..2]), None);
+        assert_eq!(username.parse(&input[..3]), Some(("abc".to_string(), None)));
+        assert_eq!(
+            username.parse(&input[..16]),
+            Some(("abcdefghijklmnop".to_string(), None))
+        );
+        assert_eq!(username.parse(&input), None);
+    }
+
+    #[test]
+    fn many_concat() {
+        let input = chars("ab-cde-!");
+        let letters = satisfy(|c| c.is_alphabetic()).many();
+        let words = letters.then_skip(char('-')).many_concat();
+        assert_eq!(
+            words.parse(&input),
+            Some((chars("abcde"), Some(&input[7..])))
+        );
+        assert_eq!(words.parse(&input[7..]), Some((vec![], Some(&input[7..]))));
+        let empty = satisfy(|c| c.is_alphabetic()).many().many_concat();
+        assert_eq!(empty.parse(&input[2..]), Some((vec![], Some(&input[2..]))));
+    }
+
+    #[test]
+    fn when_peek() {
+        let input = chars("let x");
+        let sink = Rc::new(RefCell::new(vec![]));
+        let keyword = super::string("let")
+            .trace("let", sink.clone())
+            .when_peek(char('l'));
+        assert_eq!(
+            keyword.parse(&input),
+            Some(("let".to_string(), Some(&input[3..])))
+        );
+        assert_eq!(keyword.parse(&input[3..]), None);
+        assert_eq!(sink.borrow().len(), 1);
+        let guard_only = char('l').when_peek(super::string("let"));
+        assert_eq!(guard_only.parse(&input), Some(('l', Some(&input[1..]))));
+    }
+
+    #[test]
+    fn split_on() {
+        let path = chars("/a//b/");
+        let segments = super::split_on(char('/'));
+        assert_eq!(
+            segments.parse(&path),
+            Some((
+                vec![
+                    String::new(),
+                    "a".into(),
+                    String::new(),
+                    "b".into(),
+                    String::new()
+                ],
+                None
+            ))
+        );
+        assert_eq!(segments.parse(&path[1..2]), Some((vec!["a".into()], None)));
+        assert_eq!(segments.parse(&[]), Some((vec![String::new()], None)));
+        let words = chars("a, b,c");
+        let commas = super::split_on(char(',').and(char(' ').maybe()));
+        assert_eq!(
+            commas.parse(&words),
+            Some((vec!["a".into(), "b".into(), "c".into()], None))
+        );
+    }
+
+    #[test]
+    fn tagged_choice() {
+        #[derive(Debug, PartialEq)]
+        enum Expr {
+            Num(u64),
+            Var(String),
         }
-        _ => None,
-    })
-}
+        let input = chars("x1 42");
+        let ident = satisfy(|c| c.is_alphabetic())
+            .and(satisfy(|c| c.is_alphanumeric()).many())
+            .source_text();
+        let expr = tagged_choice!(super::fast_uint() => Expr::Num, ident => Expr::Var);
+        assert_eq!(
+            expr.parse(&input),
+            Some((Expr::Var("x1".to_string()), Some(&input[2..])))
+        );
+        assert_eq!(expr.parse(&input[3..]), Some((Expr::Num(42), None)));
+        assert_eq!(expr.parse(&input[2..]), None);
+    }
 
-pub trait CollectChars {
-    fn into_string(&self) -> String;
-}
+    #[test]
+    fn sep_by_collecting_errors() {
+        let input = chars("1,x,3,,5");
+        let rows = super::fast_uint().sep_by_collecting_errors(char(','), |offset| offset);
+        assert_eq!(
+            rows.parse(&input),
+            Some(((vec![1, 3, 5], vec![2, 6]), None))
+        );
+        assert_eq!(rows.parse(&input[..3]), Some(((vec![1], vec![2]), None)));
+        assert_eq!(
+            rows.parse(&input[4..6]),
+            Some(((vec![3], vec![]), Some(&input[5..6])))
+        );
+        assert_eq!(rows.parse(&[]), Some(((vec![], vec![]), None)));
+    }
 
-impl CollectChars for char {
-    fn into_string(&self) -> String {
-        self.to_string()
+    #[test]
+    fn strip_prefix() {
+        let input = chars("0xFF");
+        let hex = satisfy(|c| c.is_ascii_hexdigit())
+            .many1()
+            .into_string()
+            .map(|digits| u32::from_str_radix(&digits, 16).unwrap());
+        let hex = super::strip_prefix("0x", hex);
+        assert_eq!(hex.parse(&input), Some((255, None)));
+        assert_eq!(hex.parse(&input[2..]), None);
     }
-}
 
-impl CollectChars for Vec<char> {
-    fn into_string(&self) -> String {
-        self.iter().collect()
+    #[test]
+    fn take_bytes() {
+        let input = [0xca, 0xfe, 0x01];
+        let header: &[u8] = &[0xca, 0xfe];
+        let rest: &[u8] = &[0x01];
+        assert_eq!(
+            super::take_bytes(2).parse(&input),
+            Some((header, Some(rest)))
+        );
+        assert_eq!(super::take_bytes(3).parse(&input), Some((&input[..], None)));
+        assert_eq!(super::take_bytes(4).parse(&input), None);
+        assert_eq!(
+            super::take_bytes(0).parse(&input),
+            Some((&input[..0], Some(&input[..])))
+        );
     }
-}
 
-impl<T: CollectChars> CollectChars for Option<T> {
-    fn into_string(&self) -> String {
-        self.as_ref().map(|t| t.into_string()).unwrap_or_default()
+    #[test]
+    fn map_res() {
+        let (input, small) = (chars("[300]"), chars("[42]"));
+        let byte = digit().many1().into_string().map_res(|digits| {
+            digits.parse::<u8>().map_err(|_| ParseError {
+                offset: 0,
+                expected: vec!["number that fits in a byte"],
+                context: vec![],
+                snippet: None,
+            })
+        });
+        let list = char('[')
+            .and(byte.context("byte"))
+            .and(char(']'))
+            .context("list");
+        let error = list.parse_with_error(&input).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "parse error at offset 1, expected number that fits in a byte in list > byte"
+        );
+        assert_eq!(list.parse(&small), Some(((('[', 42), ']'), None)));
     }
-}
 
-impl<A: CollectChars, B: CollectChars> CollectChars for (A, B) {
-    fn into_string(&self) -> String {
-        let mut s = String::new();
-        s.push_str(&self.0.into_string());
-        s.push_str(&self.1.into_string());
-        s
+    #[test]
+    fn non_empty() {
+        let input = chars("1,2;");
+        let numbers = digit().sep_by_collect::<_, Vec<_>>(char(',')).non_empty();
+        assert_eq!(
+            numbers.parse(&input),
+            Some((vec!['1', '2'], Some(&input[3..])))
+        );
+        assert_eq!(numbers.parse(&input[3..]), None);
+        assert_eq!(char('x').many().non_empty().parse(&input), None);
     }
-}
 
-impl<'a, I: 'a, O: CollectChars + 'a> Parser<'a, I, O> {
-    pub fn into_string(self) -> Parser<'a, I, String> {
-        self.map(|o| o.into_string())
+    #[cfg(feature = "instrument")]
+    #[test]
+    fn limited() {
+        let input = chars("aaaa");
+        let letters = char('a').many().into_string();
+        assert_eq!(
+            letters.limited(&input, 100),
+            Ok(Some(("aaaa".to_string(), None)))
+        );
+        assert_eq!(
+            letters.limited(&input, 3),
+            Err(StepLimitExceeded { max_steps: 3 })
+        );
+        assert_eq!(char('b').limited(&input, 100), Ok(None));
+        assert_eq!(letters.parse(&input), Some(("aaaa".to_string(), None)));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
     #[test]
-    fn single_char() {
-        let c_parser = char('c').into_string();
-        assert_eq!(c_parser.parse(&['c']).unwrap(), ("c".to_string(), None))
+    fn parse_remaining_string() {
+        let input = chars("12 rest");
+        let number = super::fast_uint();
+        assert_eq!(
+            number.parse_remaining_string(&input),
+            Some((12, " rest".to_string()))
+        );
+        assert_eq!(
+            number.parse_remaining_string(&input[..2]),
+            Some((12, String::new()))
+        );
+        assert_eq!(number.parse_remaining_string(&input[2..]), None);
     }
+
     #[test]
-    fn single_digit() {
-        let digit_parser = digit().into_string();
-        assert_eq!(digit_parser.parse(&['1']).unwrap(), ("1".to_string(), None))
+    fn cursor() {
+        fn many_until_balanced<'a>(item: StringParser<'a, char>) -> StringParser<'a, String> {
+            Parser::new(move |input: &'a [char]| {
+                let mut cursor = Cursor::new(input);
+                let mut depth = 0usize;
+                let mut items = String::new();
+                loop {
+                    let (c, next) = item.parse_at(cursor)?;
+                    depth = match c {
+                        '(' => depth + 1,
+                        ')' => depth.checked_sub(1)?,
+                        _ => depth,
+                    };
+                    items.push(c);
+                    cursor = next;
+                    if depth == 0 {
+                        return Some((items, cursor.rest()));
+                    }
+                }
+            })
+        }
+
+        let input = chars("(a(b))c");
+        let group = many_until_balanced(satisfy(|_| true));
+        assert_eq!(
+            group.parse(&input),
+            Some(("(a(b))".to_string(), Some(&input[6..])))
+        );
+        assert_eq!(group.parse(&input[..5]), None);
+
+        let start = Cursor::new(&input[..]);
+        let checkpoint = start.advance(2).unwrap();
+        assert_eq!(checkpoint.position(), 2);
+        assert_eq!(checkpoint.remaining(), &input[2..]);
+        let (_, after) = char('(').parse_at(checkpoint).unwrap();
+        assert_eq!(after.position(), 3);
+        assert_eq!(char('(').parse_at(after), None);
+        let (spanned, _) = char('(').spanned().parse_at(checkpoint).unwrap();
+        assert_eq!(spanned.start, checkpoint.position());
+        assert_eq!(checkpoint.advance(5).map(|end| end.rest()), Some(None));
+        assert!(checkpoint.advance(6).is_none());
     }
+
     #[test]
-    fn or() {
-        let c_parser = char('c');
-        let digit_parser = digit();
-        let c_or_digit_parser = c_parser.or(digit_parser).into_string();
+    fn sep_by_pairs() {
+        let input = chars("Accept:a\nVia:x\nAccept:b\n");
+        let name = satisfy(|c| c.is_alphabetic()).many().into_string();
+        let value = satisfy(|c| c != '\n').many().into_string();
+        let header = name.then_skip(char(':')).and(value);
+        let headers = header.sep_by_pairs(char('\n'));
+        let pair = |k: &str, v: &str| (k.to_string(), v.to_string());
         assert_eq!(
-            c_or_digit_parser.parse(&['c']),
-            Some(("c".to_string(), None))
+            headers.parse(&input),
+            Some((
+                vec![pair("Accept", "a"), pair("Via", "x"), pair("Accept", "b")],
+                Some(&input[input.len() - 1..])
+            ))
         );
         assert_eq!(
-            c_or_digit_parser.parse(&['1']),
-            Some(("1".to_string(), None))
+            headers.parse(&input[..0]),
+            Some((vec![], Some(&input[..0])))
         );
-        assert_eq!(c_or_digit_parser.parse(&['a']), None);
     }
 
     #[test]
-    fn and() {
-        let c_parser = char('c');
-        let d_parser = char('d');
-        let c_and_d_parser = c_parser.and(d_parser).into_string();
-        let c: &[char] = &['c'];
+    fn case_insensitive() {
+        let input = chars("<HTML>");
+        let lower = chars("<html>");
+        let mixed_case = chars("aAaAb");
+        let tag = super::string("html").case_insensitive();
         assert_eq!(
-            c_and_d_parser.parse(&['c', 'd']),
-            Some(("cd".to_string(), None))
+            tag.parse(&input[1..]),
+            Some(("html".to_string(), Some(&input[5..])))
         );
-        assert_eq!(c_and_d_parser.parse(&['c']), None);
-        assert_eq!(c_and_d_parser.parse(&['c', 'c']), None);
+        let written = super::string("html").case_insensitive().source_text();
         assert_eq!(
-            c_and_d_parser.parse(&['c', 'd', 'c']),
-            Some(("cd".to_string(), Some(c)))
+            written.parse(&input[1..]),
+            Some(("HTML".to_string(), Some(&input[5..])))
         );
-        assert_eq!(c_and_d_parser.parse(&['a']), None);
+        let upper = super::char_class("A-Z").case_insensitive();
+        assert_eq!(upper.parse(&input[1..]), Some(('H', Some(&input[2..]))));
+        assert_eq!(upper.parse(&lower[1..]), Some(('h', Some(&lower[2..]))));
+        let lowercase = satisfy(|c| c.is_ascii_lowercase()).case_insensitive();
+        assert_eq!(lowercase.parse(&input[1..]), Some(('H', Some(&input[2..]))));
+        assert_eq!(lowercase.parse(&input), None);
+        let calls = Rc::new(Cell::new(0));
+        let counted = calls.clone();
+        let only_a = satisfy(move |c| {
+            counted.set(counted.get() + 1);
+            c == 'a'
+        });
+        assert_eq!(only_a.parse(&['b']), None);
+        assert_eq!(calls.get(), 1);
+        let only_a = only_a.case_insensitive();
+        assert_eq!(only_a.parse(&['b']), None);
+        assert_eq!(calls.get(), 3);
+        let repeated = char('a').run().case_insensitive();
+        assert_eq!(
+            repeated.parse(&mixed_case),
+            Some((('a', 4), Some(&mixed_case[4..])))
+        );
+        let mixed = char('<').and(super::string("HTML")).and(char('>'));
+        assert!(mixed.parse(&input).is_some());
+        let strict = char('<').and(super::string("html"));
+        assert_eq!(strict.parse(&input), None);
     }
 
     #[test]
-    fn then_maybe() {
-        let c_parser = char('c');
-        let d_parser = char('d');
-        let c: &[char] = &['c'];
-        let c_and_then_maybe_d_parser = c_parser.then_maybe(d_parser).into_string();
+    fn max_depth() {
+        let (flat, nested, unbalanced) = (chars("()()"), chars("(a(b)((c)))d"), chars("((a)"));
+        let depth = super::max_depth('(', ')');
+        assert_eq!(depth.parse(&flat), Some((1, Some(&flat[2..]))));
+        assert_eq!(depth.parse(&nested), Some((3, Some(&nested[11..]))));
+        assert_eq!(depth.parse(&unbalanced), None);
+        assert_eq!(depth.parse(&nested[1..]), None);
+    }
+
+    #[test]
+    fn expect() {
+        let (unclosed, other) = (chars("(1]"), chars("x(1]"));
+        let group = char('(')
+            .and(digit())
+            .and(char(')').expect("closing parenthesis"))
+            .map(|((_, d), _)| d);
+        let atom = group.or(satisfy(|_| true));
+        let error = atom.parse_with_error(&unclosed).unwrap_err();
         assert_eq!(
-            c_and_then_maybe_d_parser.parse(&['c', 'd']),
-            Some(("cd".to_string(), None))
+            error.to_string(),
+            "parse error at offset 2, expected closing parenthesis"
         );
+        assert_eq!(atom.parse(&unclosed), None);
+        assert_eq!(atom.parse(&other), Some(('x', Some(&other[1..]))));
+        assert!(atom.parse_all(&unclosed).is_err());
+        assert_eq!(char('a').or(char('b')).parse_all(&['b']), Ok('b'));
+        let recovered = atom.maybe().and(char('a').or(char('(')));
         assert_eq!(
-            c_and_then_maybe_d_parser.parse(&['c', 'c']),
-            Some(("c".to_string(), Some(c)))
+            recovered.parse(&unclosed),
+            Some(((None, '('), Some(&unclosed[1..])))
         );
+
+        let closing = char(')').expect("closing parenthesis").into_fn();
+        assert_eq!(closing(&unclosed[2..]), None);
+        assert_eq!(char('a').or(char('b')).parse_all(&['b']), Ok('b'));
+
+        let committed = || char('(').and(char(')').expect("closing parenthesis"));
+        let fallback = || char('(').and(digit());
+        assert_eq!(committed().or(fallback()).parse(&unclosed), None);
+        assert_eq!(committed().either(fallback()).parse(&unclosed), None);
+        let longest = Parser::choice_peek_longest(vec![committed(), fallback()]);
+        assert_eq!(longest.parse(&unclosed), None);
+        let alternatives = [&committed(), &fallback()];
+        assert_eq!(Parser::choice_ref(&alternatives, &unclosed), None);
+        assert!(Parser::choice_ref(&alternatives[1..], &unclosed).is_some());
+
+        let c = chars("c");
+        let many = Parser::choice(vec![
+            char('a').expect("a").many().and(char('b')).map(|_| 'x'),
+            char('c'),
+        ]);
+        assert_eq!(many.parse(&c), Some(('c', None)));
+        let maybe = char('a')
+            .expect("a")
+            .maybe()
+            .and(char('b'))
+            .map(|_| 'x')
+            .or(char('c'));
+        assert_eq!(maybe.parse(&c), Some(('c', None)));
+        let value = char('a').expect("a").or_value('x').and(char('b'));
         assert_eq!(
-            c_and_then_maybe_d_parser.parse(&['c', 'd', 'c']),
-            Some(("cd".to_string(), Some(c)))
+            value.map(|_| 'x').or(char('c')).parse(&c),
+            Some(('c', None))
         );
-        assert_eq!(c_and_then_maybe_d_parser.parse(&['d', 'c']), None);
+
+        let cached = Rc::new(committed().cache_failures().or(fallback()));
+        let twice = Parser::new(move |input| {
+            Some(((cached.0(input).is_some(), cached.0(input).is_some()), None))
+        });
+        assert_eq!(twice.parse(&unclosed), Some(((false, false), None)));
     }
+
     #[test]
-    fn many() {
-        let many_c_parser = char('c').many().into_string();
-        let d: &[char] = &['d'];
+    fn parse_lines() {
+        let log = chars("12 ok\r\nbad\n\n7\n");
+        let number = super::fast_uint();
         assert_eq!(
-            many_c_parser.parse(&['c', 'c', 'c']),
-            Some(("ccc".to_string(), None))
+            super::parse_lines(&number, &log).collect::<Vec<_>>(),
+            vec![Some(12), None, None, Some(7)]
         );
+        assert_eq!(super::parse_lines(&number, &[]).count(), 0);
+    }
+
+    #[test]
+    fn parse_map_complete() {
+        let input = chars("42;");
+        let number = super::fast_uint();
+        let double = |n: u64| n * 2;
+        assert_eq!(number.parse_map_complete(&input[..2], double), Some(84));
+        assert_eq!(number.parse_map_complete(&input, double), None);
+        assert_eq!(number.parse_map_complete(&input[2..], double), None);
+        let empty = Parser::new(|input: &[char]| Some((1, Some(input))));
+        assert_eq!(empty.parse_map_complete(&input[..0], double), Some(2));
+    }
+
+    #[test]
+    fn with_rest_text() {
+        let input = chars("Length: 5\n\nhello");
+        let header = super::string("Length: ")
+            .and(super::fast_uint())
+            .then_skip(super::string("\n\n"))
+            .map(|(_, len)| len);
         assert_eq!(
-            many_c_parser.parse(&['c', 'c', 'd']),
-            Some(("cc".to_string(), Some(d)))
+            header.with_rest_text().parse(&input),
+            Some(((5, "hello".to_string()), None))
         );
+        let whole = super::string("Length: 5\n\nhello").with_rest_text();
         assert_eq!(
-            many_c_parser.parse(&['c', 'd']),
-            Some(("c".to_string(), Some(d)))
+            whole.parse(&input).map(|((_, rest), _)| rest),
+            Some(String::new())
         );
     }
 
     #[test]
-    fn many1() {
-        let many1_c_parser = char('c').many1().into_string();
+    fn line_ending() {
+        let input = chars("a\r\nb\nc\rd\n");
+        let ending = super::line_ending();
         assert_eq!(
-            many1_c_parser.parse(&['c', 'c', 'c']),
-            Some(("ccc".to_string(), None))
+            ending.parse(&input[1..]),
+            Some(("\r\n".to_string(), Some(&input[3..])))
         );
-        assert_eq!(many1_c_parser.parse(&['d']), None);
+        assert_eq!(
+            ending.parse(&input[4..]),
+            Some(("\n".to_string(), Some(&input[5..])))
+        );
+        assert_eq!(
+            ending.parse(&input[6..]),
+            Some(("\r".to_string(), Some(&input[7..])))
+        );
+        let lines = super::lines();
+        let expected: Vec<String> = vec!["a".into(), "b".into(), "c".into(), "d".into()];
+        assert_eq!(lines.parse(&input), Some((expected, None)));
+        assert_eq!(
+            lines.parse(&input[..3]),
+            Some((vec!["a".to_string()], None))
+        );
+        assert_eq!(lines.parse(&[]), Some((vec![], None)));
     }
 
     #[test]
-    fn identifier() {
-        let ident_parser = satisfy(|c: char| c.is_alphabetic() || c == '_')
-            .then_maybe(satisfy(|c: char| c.is_alphanumeric() || c == '_').many())
-            .into_string();
+    fn peek_n() {
+        let input = chars("a<=");
+        let two = super::peek_n(2);
+        assert_eq!(
+            two.parse(&input[1..]),
+            Some((chars("<="), Some(&input[1..])))
+        );
+        assert_eq!(
+            two.parse(&input[2..]),
+            Some((chars("="), Some(&input[2..])))
+        );
+        assert_eq!(two.parse(&input[3..]), Some((vec![], Some(&input[3..]))));
+        let bytes: &[u8] = &[1, 2, 3];
+        assert_eq!(
+            super::peek_n(5).parse(bytes),
+            Some((vec![1, 2, 3], Some(bytes)))
+        );
+    }
 
+    #[test]
+    fn lex() {
+        #[derive(Debug, PartialEq)]
+        enum Token {
+            Num(u64),
+            Op(char),
+        }
+        let input = chars("12 + (3*45)");
+        let token = tagged_choice!(
+            super::fast_uint() => Token::Num,
+            super::char_class("-+*/()") => Token::Op,
+        );
+        let whitespace = satisfy(|c| c.is_whitespace());
         assert_eq!(
-            ident_parser.parse(&['h', 'e', 'l', 'l', 'o', '_', 'w', 'o', 'r', 'l', 'd']),
-            Some(("hello_world".to_string(), None))
+            super::lex(token, whitespace, &input),
+            Ok(vec![
+                (Token::Num(12), 0..2),
+                (Token::Op('+'), 3..4),
+                (Token::Op('('), 5..6),
+                (Token::Num(3), 6..7),
+                (Token::Op('*'), 7..8),
+                (Token::Num(45), 8..10),
+                (Token::Op(')'), 10..11),
+            ])
+        );
+        let bad = chars(" 1 ? 2");
+        let token = super::fast_uint().label("number");
+        let error = super::lex(token, satisfy(|c| c == ' '), &bad).unwrap_err();
+        assert_eq!((error.offset, error.expected), (3, vec!["number"]));
+    }
+
+    #[test]
+    fn one_of_strings_exact() {
+        let input = chars("GETS get");
+        let method = super::one_of_strings_exact(&["GET", "GETS", "Get"]);
+        assert_eq!(
+            method.parse(&input),
+            Some(("GETS".to_string(), Some(&input[4..])))
+        );
+        assert_eq!(method.parse(&input[5..]), None);
+        assert_eq!(method.case_insensitive().parse(&input[5..]), None);
+    }
+
+    #[test]
+    fn spans_of() {
+        let input = chars("let x1 = 42;");
+        let highlight = Parser::choice(vec![
+            super::word("let").spans_of("keyword"),
+            super::fast_uint().spans_of("number"),
+            satisfy(|c| c.is_alphanumeric()).many1().spans_of("ident"),
+            satisfy(|c| !c.is_alphanumeric()).spans_of("punct"),
+        ])
+        .many();
+        let (spans, _) = highlight.parse(&input).unwrap();
+        let categories: Vec<_> = spans.iter().map(|(_, category)| *category).collect();
+        assert_eq!(
+            categories,
+            vec!["keyword", "punct", "ident", "punct", "punct", "punct", "number", "punct"]
+        );
+        assert!(spans.windows(2).all(|w| w[0].0.end == w[1].0.start));
+        assert_eq!(spans[2].0, 4..6);
+        assert_eq!(spans[6].0, 9..11);
+    }
+
+    #[test]
+    fn digits_radix() {
+        let input = chars("1019z");
+        let value = |radix, input| super::digits_radix(radix).parse(input).map(|(n, _)| n);
+        assert_eq!(value(2, &input[..]), Some(0b101));
+        assert_eq!(value(8, &input[..]), Some(0o101));
+        assert_eq!(value(10, &input[..]), Some(1019));
+        assert_eq!(value(16, &input[..]), Some(0x1019));
+        assert_eq!(value(36, &input[..]), Some(1_681_271));
+        assert_eq!(value(16, &input[4..]), None);
+        let max = chars("ffffffffffffffff");
+        assert_eq!(value(16, &max[..]), Some(u64::MAX));
+        let overflow = chars("10000000000000000");
+        assert_eq!(value(16, &overflow[..]), None);
+    }
+
+    #[test]
+    fn intern() {
+        let input = chars("ab cd ab;");
+        let ident = satisfy(|c| c.is_alphabetic())
+            .many1()
+            .into_string()
+            .intern();
+        let idents = ident.then_skip(char(' ').maybe()).many();
+        let (names, _) = idents.parse(&input).unwrap();
+        assert_eq!(
+            names.iter().map(|n| &**n).collect::<Vec<_>>(),
+            ["ab", "cd", "ab"]
         );
+        assert!(Rc::ptr_eq(&names[0], &names[2]));
+        assert!(!Rc::ptr_eq(&names[0], &names[1]));
+        let (again, _) = idents.parse(&input).unwrap();
+        assert!(!Rc::ptr_eq(&names[0], &again[0]));
+    }
+
+    #[test]
+    fn fields() {
+        let (row, leftover) = (chars("1,22,,4"), chars("1,2x"));
+        let cell = super::fast_uint().maybe();
+        let cells = super::fields(cell, char(','));
+        assert_eq!(
+            cells.parse(&row),
+            Some((vec![Some(1), Some(22), None, Some(4)], None))
+        );
+        let required = super::fields(super::fast_uint(), char(','));
+        assert_eq!(required.parse(&row), None);
+        assert_eq!(required.parse(&row[..4]), Some((vec![1, 22], None)));
+        assert_eq!(required.parse(&leftover), None);
+    }
+
+    #[test]
+    fn statements() {
+        let (input, missing_sync) = (chars("x=1;y=?;z=3"), chars("x=1 y=2;z=3"));
+        let assign = satisfy(|c| c.is_alphabetic())
+            .then_skip(char('='))
+            .and(super::expect_char(|c| c.is_ascii_digit(), "digit"));
+        let program = super::statements(assign, char(';').skip());
+        let (parsed, rest) = program.parse(&input).unwrap();
+        assert_eq!(rest, None);
+        assert_eq!(parsed[0], Ok(('x', '1')));
+        assert_eq!(
+            parsed[1].as_ref().map_err(|error| error.to_string()),
+            Err("parse error at offset 6, expected digit".to_string())
+        );
+        assert_eq!(parsed[2], Ok(('z', '3')));
+        assert_eq!(parsed.len(), 3);
+        let (parsed, _) = program.parse(&input[..7]).unwrap();
+        assert!(parsed[1].is_err());
+        let (parsed, _) = program.parse(&missing_sync).unwrap();
+        assert_eq!(
+            parsed.iter().map(Result::is_ok).collect::<Vec<_>>(),
+            [false, true]
+        );
+
+        let zero_width = chars("y;x");
+        let program = super::statements(char('x'), char(';').maybe().skip());
+        let (parsed, rest) = program.parse(&zero_width).unwrap();
+        assert_eq!(rest, None);
+        assert!(parsed[0].is_err());
+        assert_eq!(parsed[1..], [Ok('x')]);
+    }
+
+    #[test]
+    fn many_map_while() {
+        let input = chars("12309");
+        let digits = digit().many_map_while(|c| c.to_digit(10).filter(|d| *d != 0));
+        assert_eq!(
+            digits.parse(&input),
+            Some((vec![1, 2, 3], Some(&input[3..])))
+        );
+        assert_eq!(digits.parse(&input[..2]), Some((vec![1, 2], None)));
+        let zero_width = super::peek_char(|c| c == '1').many_map_while(|c| Some(*c));
+        assert_eq!(zero_width.parse(&input), Some((vec![], Some(&input[..]))));
+    }
+
+    #[test]
+    fn sequence() {
+        let input = chars("abc!");
+        let abc = Parser::sequence("abc".chars().map(char).collect());
+        assert_eq!(abc.parse(&input), Some((chars("abc"), Some(&input[3..]))));
+        assert_eq!(abc.parse(&input[1..]), None);
+        assert_eq!(abc.parse(&input[..2]), None);
+        let empty = Parser::<char, char>::sequence(vec![]);
+        assert_eq!(empty.parse(&input), Some((vec![], Some(&input[..]))));
+    }
+
+    #[test]
+    fn parse_with_tree() {
+        let input = chars("ab");
+        let letters = char('a').label("a").or(char('b').label("b")).many();
+        let (result, roots) = letters.parse_with_tree(&input);
+        assert_eq!(result, Some((chars("ab"), None)));
+        let leaf = |tag, offset, outcome| ParseTree {
+            tag,
+            offset,
+            outcome,
+            children: vec![],
+        };
+        let matched = |consumed| TraceOutcome::Matched { consumed };
+        assert_eq!(
+            roots,
+            vec![ParseTree {
+                tag: "many",
+                offset: 0,
+                outcome: matched(2),
+                children: vec![
+                    ParseTree {
+                        tag: "or",
+                        offset: 0,
+                        outcome: matched(1),
+                        children: vec![leaf("a", 0, matched(1))],
+                    },
+                    ParseTree {
+                        tag: "or",
+                        offset: 1,
+                        outcome: matched(1),
+                        children: vec![
+                            leaf("a", 1, TraceOutcome::Failed),
+                            leaf("b", 1, matched(1))
+                        ],
+                    },
+                ],
+            }]
+        );
+        assert_eq!(letters.parse_with_tree(&input[..0]).1[0].children.len(), 1);
+    }
+
+    #[test]
+    fn choice_peek_longest() {
+        let input = chars("a <<= b");
+        let op = |s| super::string_static(s);
+        let tie = op("<<").map(|_| "shadowed");
+        let operator = Parser::choice_peek_longest(vec![op("<"), op("<="), op("<<"), tie]);
+        assert_eq!(operator.parse(&input[2..]), Some(("<<", Some(&input[4..]))));
+        assert_eq!(operator.parse(&input[3..]), Some(("<=", Some(&input[5..]))));
+        assert_eq!(operator.parse(&input[3..4]), Some(("<", None)));
+        assert_eq!(operator.parse(&input[..]), None);
+    }
+
+    #[test]
+    fn count_while() {
+        let input = chars("    x");
+        let indent = super::count_while(|c| c == ' ');
+        assert_eq!(indent.parse(&input), Some((4, Some(&input[4..]))));
+        assert_eq!(indent.parse(&input[4..]), Some((0, Some(&input[4..]))));
+        assert_eq!(indent.parse(&input[..2]), Some((2, None)));
     }
 }